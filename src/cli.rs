@@ -0,0 +1,199 @@
+use ray_tracing::grfx::image::ImageFormat;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: ray_tracing [OPTIONS]
+
+Renders the scene given (or the default random spheres) and shows it in a window.
+
+Options:
+  -s, --scene <FILE>     scene file to render (see grfx::scene for the format)
+  -W, --width <N>        image width in pixels
+  -H, --height <N>       image height in pixels
+  -n, --samples <N>      samples (rays) per pixel
+  -d, --depth <N>        maximum number of bounces per ray
+      --seed <N>         seed for the random sampling (and the random spheres),
+                         the same seed always gives the same image
+      --spectral         trace wavelengths instead of RGB colors (dispersion)
+  -j, --threads <N>      render threads, 0 uses all the cores
+  -o, --output <FILE>    write the image to FILE
+  -f, --format <FORMAT>  output format: png, ppm, ppm-ascii, pfm or exr
+                         (default from the extension)
+      --headless         don't open a window, only write the output file
+  -h, --help             print this help
+";
+
+///
+/// Command line options, settings not given keep the values of the scene
+///
+#[derive(Debug, Default)]
+pub struct Options {
+    pub scene: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples: Option<u32>,
+    pub depth: Option<u32>,
+    pub seed: Option<u64>,
+    pub spectral: bool,
+    pub threads: Option<usize>,
+    pub output: Option<String>,
+    pub format: Option<ImageFormat>,
+    pub headless: bool,
+    pub help: bool,
+}
+
+impl Options {
+    ///
+    /// Parse the arguments given to the program (without the program name)
+    ///
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-s" | "--scene" => options.scene = Some(value(&arg, &mut args)?),
+                "-W" | "--width" => options.width = Some(number(&arg, &mut args)?),
+                "-H" | "--height" => options.height = Some(number(&arg, &mut args)?),
+                "-n" | "--samples" => options.samples = Some(number(&arg, &mut args)?),
+                "-d" | "--depth" => options.depth = Some(number(&arg, &mut args)?),
+                "--seed" => options.seed = Some(number(&arg, &mut args)?),
+                "--spectral" => options.spectral = true,
+                "-j" | "--threads" => options.threads = Some(number(&arg, &mut args)?),
+                "-o" | "--output" => options.output = Some(value(&arg, &mut args)?),
+                "-f" | "--format" => {
+                    let name = value(&arg, &mut args)?;
+                    match ImageFormat::from_name(&name) {
+                        Some(format) => options.format = Some(format),
+                        None => return Err(format!("unsupported output format '{}'", name)),
+                    }
+                }
+                "--headless" => options.headless = true,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+        if options.width == Some(0) || options.height == Some(0) {
+            return Err("image size can't be 0".to_string());
+        }
+        if options.headless && options.output.is_none() {
+            return Err("--headless requires an --output file".to_string());
+        }
+        if let (Some(output), None) = (&options.output, options.format) {
+            if ImageFormat::from_filename(output).is_none() {
+                return Err(format!(
+                    "can't tell the format of '{}', use a .png/.ppm/.pfm/.exr extension or --format",
+                    output
+                ));
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Retrieve the value following an option
+fn value<I: Iterator<Item = String>>(option: &str, args: &mut I) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("missing value for {}", option))
+}
+
+/// Retrieve the numeric value following an option
+fn number<T: FromStr, I: Iterator<Item = String>>(option: &str, args: &mut I) -> Result<T, String> {
+    let text = value(option, args)?;
+    text.parse()
+        .map_err(|_| format!("invalid value '{}' for {}", text, option))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use ray_tracing::grfx::image::ImageFormat;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn short_and_long_forms() {
+        let short = parse(&[
+            "-s", "a.scene", "-W", "320", "-H", "200", "-n", "8", "-d", "5", "-j", "2",
+        ])
+        .unwrap();
+        let long = parse(&[
+            "--scene",
+            "a.scene",
+            "--width",
+            "320",
+            "--height",
+            "200",
+            "--samples",
+            "8",
+            "--depth",
+            "5",
+            "--threads",
+            "2",
+        ])
+        .unwrap();
+        for options in [short, long] {
+            assert_eq!(options.scene.as_deref(), Some("a.scene"));
+            assert_eq!((options.width, options.height), (Some(320), Some(200)));
+            assert_eq!(
+                (options.samples, options.depth, options.threads),
+                (Some(8), Some(5), Some(2))
+            );
+        }
+        let flags = parse(&["--seed", "7", "-h"]).unwrap();
+        assert_eq!(flags.seed, Some(7));
+        assert!(flags.help && !flags.headless);
+        assert!(parse(&[]).unwrap().scene.is_none());
+    }
+
+    #[test]
+    fn missing_invalid_and_unknown_arguments() {
+        assert_eq!(
+            parse(&["--width"]).unwrap_err(),
+            "missing value for --width"
+        );
+        assert_eq!(
+            parse(&["-n", "many"]).unwrap_err(),
+            "invalid value 'many' for -n"
+        );
+        assert_eq!(parse(&["--fast"]).unwrap_err(), "unknown argument '--fast'");
+        assert_eq!(parse(&["-W", "0"]).unwrap_err(), "image size can't be 0");
+        assert_eq!(
+            parse(&["--headless"]).unwrap_err(),
+            "--headless requires an --output file"
+        );
+        assert_eq!(
+            parse(&["--format", "gif"]).unwrap_err(),
+            "unsupported output format 'gif'"
+        );
+    }
+
+    #[test]
+    fn output_format_from_flag_or_extension() {
+        let png = parse(&["-o", "out.png", "--headless"]).unwrap();
+        assert_eq!(
+            (png.output.as_deref(), png.format, png.headless),
+            (Some("out.png"), None, true)
+        );
+        let ppm = parse(&["-o", "out", "-f", "ppm"]).unwrap();
+        assert_eq!(ppm.format, Some(ImageFormat::Ppm));
+        assert!(parse(&["-o", "out"])
+            .unwrap_err()
+            .starts_with("can't tell the format of 'out'"));
+    }
+
+    #[test]
+    fn hdr_output_formats() {
+        let exr = parse(&["-o", "out", "-f", "exr"]).unwrap();
+        assert_eq!(exr.format, Some(ImageFormat::Exr));
+        let pfm = parse(&["--output", "out", "--format", "pfm"]).unwrap();
+        assert_eq!(pfm.format, Some(ImageFormat::Pfm));
+        assert!(parse(&["-o", "out.exr"]).unwrap().format.is_none());
+    }
+
+    #[test]
+    fn spectral_flag() {
+        assert!(parse(&["--spectral"]).unwrap().spectral);
+        assert!(!parse(&[]).unwrap().spectral);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::grfx::ray::Ray;
+use crate::grfx::vector::Vec3D;
+
+///
+/// Axis aligned bounding box defined by its two opposite corners.
+///
+/// Used by acceleration structures (see bvh) to quickly discard rays
+/// that cannot possibly hit the objects contained in the box.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    min: Vec3D,
+    max: Vec3D,
+}
+
+impl Aabb {
+    /// Create a box from its minimum and maximum corners
+    pub fn new(min: Vec3D, max: Vec3D) -> Self {
+        Self { min, max }
+    }
+
+    ///
+    /// Create an empty box (min at +infinity and max at -infinity).
+    /// Growing an empty box by any other box yields that other box.
+    ///
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3D::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vec3D::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    /// Retrieve minimum corner
+    pub fn min(&self) -> Vec3D {
+        self.min
+    }
+
+    /// Retrieve maximum corner
+    pub fn max(&self) -> Vec3D {
+        self.max
+    }
+
+    /// Smallest box containing both boxes given
+    pub fn surrounding(left: &Aabb, right: &Aabb) -> Self {
+        Self {
+            min: Vec3D::min(&left.min, &right.min),
+            max: Vec3D::max(&left.max, &right.max),
+        }
+    }
+
+    /// Smallest box containing this box and the given point
+    pub fn grow(&self, point: Vec3D) -> Self {
+        Self {
+            min: Vec3D::min(&self.min, &point),
+            max: Vec3D::max(&self.max, &point),
+        }
+    }
+
+    /// Center point of the box
+    pub fn centroid(&self) -> Vec3D {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Surface area of the box, used by the surface area heuristic (SAH)
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    /// Index (0 = x, 1 = y, 2 = z) of the axis along which the box is the longest
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    ///
+    /// Determine whether the ray crosses the box somewhere between min and max (slab method).
+    ///
+    pub fn hit(&self, ray: &Ray, mut min: f32, mut max: f32) -> bool {
+        let origin = ray.origin();
+        let direction = ray.direction();
+        for axis in 0..3 {
+            let inv_d = 1.0 / direction.axis(axis);
+            let mut t0 = (self.min.axis(axis) - origin.axis(axis)) * inv_d;
+            let mut t1 = (self.max.axis(axis) - origin.axis(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // f32::max/min ignore NaN, which happens when the ray lies on the slab plane
+            min = min.max(t0);
+            max = max.min(t1);
+            if max <= min {
+                return false;
+            }
+        }
+        true
+    }
+}
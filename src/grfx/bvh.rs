@@ -0,0 +1,199 @@
+use crate::grfx::aabb::Aabb;
+use crate::grfx::ray::Ray;
+use crate::grfx::shape::{HitList, HitRecord, Hitable};
+use std::sync::Arc;
+
+/// Leaves holding this many items (or less) are not split further unless it's cheaper
+const MAX_LEAF_ITEMS: usize = 4;
+/// Number of buckets the centroids are binned into when evaluating split candidates
+const SAH_BUCKETS: usize = 12;
+/// Cost of visiting a node relative to the cost of intersecting a primitive
+const TRAVERSAL_COST: f32 = 0.125;
+
+///
+/// Bounding volume hierarchy node.
+///
+/// Groups hitable items into a binary tree of bounding boxes so that a ray only
+/// needs to be tested against the items whose boxes it crosses, instead of every item
+/// in the scene like HitList does. Splits are chosen with the surface area heuristic.
+///
+/// Items without a bounding box (i.e. infinite shapes) cannot be partitioned, they
+/// are kept aside and tested against every ray.
+///
+pub struct BvhNode {
+    bbox: Aabb,
+    contents: Contents,
+    unbounded: Vec<Arc<dyn Hitable>>,
+}
+
+enum Contents {
+    Leaf(Vec<Arc<dyn Hitable>>),
+    Branch(Box<BvhNode>, Box<BvhNode>),
+}
+
+/// Item being sorted into the tree along with its (cached) bounding box
+struct Entry {
+    bbox: Aabb,
+    centroid: f32,
+    item: Arc<dyn Hitable>,
+}
+
+impl BvhNode {
+    ///
+    /// Build a hierarchy out of all the items in the list given
+    ///
+    pub fn new(list: HitList) -> Self {
+        let mut entries = Vec::new();
+        let mut unbounded = Vec::new();
+        for item in list.into_items() {
+            match item.bounding_box() {
+                Some(bbox) => entries.push(Entry {
+                    bbox,
+                    centroid: 0.0,
+                    item,
+                }),
+                None => unbounded.push(item),
+            }
+        }
+        let mut root = Self::build(entries);
+        root.unbounded = unbounded;
+        root
+    }
+
+    fn leaf(bbox: Aabb, entries: Vec<Entry>) -> Self {
+        Self {
+            bbox,
+            contents: Contents::Leaf(entries.into_iter().map(|e| e.item).collect()),
+            unbounded: Vec::new(),
+        }
+    }
+
+    fn build(mut entries: Vec<Entry>) -> Self {
+        let bbox = entries
+            .iter()
+            .fold(Aabb::empty(), |acc, e| Aabb::surrounding(&acc, &e.bbox));
+        if entries.len() <= 2 {
+            return Self::leaf(bbox, entries);
+        }
+
+        // split along the axis where the centroids are the most spread out
+        let centroids = entries
+            .iter()
+            .fold(Aabb::empty(), |acc, e| acc.grow(e.bbox.centroid()));
+        let axis = centroids.longest_axis();
+        let start = centroids.min().axis(axis);
+        let extent = centroids.max().axis(axis) - start;
+        for entry in entries.iter_mut() {
+            entry.centroid = entry.bbox.centroid().axis(axis);
+        }
+
+        if extent <= 0.0 {
+            // all centroids overlap, no heuristic can separate them
+            if entries.len() <= MAX_LEAF_ITEMS {
+                return Self::leaf(bbox, entries);
+            }
+            let right = entries.split_off(entries.len() / 2);
+            return Self::branch(bbox, entries, right);
+        }
+
+        // bin the items and evaluate the cost of splitting after each bucket
+        let bucket_of = |centroid: f32| {
+            (((centroid - start) / extent * SAH_BUCKETS as f32) as usize).min(SAH_BUCKETS - 1)
+        };
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut boxes = [Aabb::empty(); SAH_BUCKETS];
+        for entry in &entries {
+            let bucket = bucket_of(entry.centroid);
+            counts[bucket] += 1;
+            boxes[bucket] = Aabb::surrounding(&boxes[bucket], &entry.bbox);
+        }
+
+        let mut best_split = 0;
+        let mut best_cost = f32::INFINITY;
+        for split in 0..SAH_BUCKETS - 1 {
+            let (left_count, left_box) = Self::merge(&counts[..=split], &boxes[..=split]);
+            let (right_count, right_box) = Self::merge(&counts[split + 1..], &boxes[split + 1..]);
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (left_count as f32 * left_box.surface_area()
+                    + right_count as f32 * right_box.surface_area())
+                    / bbox.surface_area();
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        if entries.len() <= MAX_LEAF_ITEMS && best_cost >= entries.len() as f32 {
+            return Self::leaf(bbox, entries);
+        }
+        let (left, right): (Vec<Entry>, Vec<Entry>) = entries
+            .into_iter()
+            .partition(|e| bucket_of(e.centroid) <= best_split);
+        Self::branch(bbox, left, right)
+    }
+
+    fn branch(bbox: Aabb, left: Vec<Entry>, right: Vec<Entry>) -> Self {
+        Self {
+            bbox,
+            contents: Contents::Branch(Box::new(Self::build(left)), Box::new(Self::build(right))),
+            unbounded: Vec::new(),
+        }
+    }
+
+    /// Total item count and bounding box of a range of buckets
+    fn merge(counts: &[usize], boxes: &[Aabb]) -> (usize, Aabb) {
+        counts
+            .iter()
+            .zip(boxes)
+            .fold((0, Aabb::empty()), |(count, bbox), (c, b)| {
+                (count + c, Aabb::surrounding(&bbox, b))
+            })
+    }
+}
+
+impl Hitable for BvhNode {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let mut closest_so_far = max;
+        let mut hit_anything = false;
+        for item in &self.unbounded {
+            if item.hit(ray, min, closest_so_far, record) {
+                hit_anything = true;
+                closest_so_far = record.t;
+            }
+        }
+        if !self.bbox.hit(ray, min, closest_so_far) {
+            return hit_anything;
+        }
+        match &self.contents {
+            Contents::Leaf(items) => {
+                for item in items {
+                    if item.hit(ray, min, closest_so_far, record) {
+                        hit_anything = true;
+                        closest_so_far = record.t;
+                    }
+                }
+            }
+            Contents::Branch(left, right) => {
+                if left.hit(ray, min, closest_so_far, record) {
+                    hit_anything = true;
+                    closest_so_far = record.t;
+                }
+                if right.hit(ray, min, closest_so_far, record) {
+                    hit_anything = true;
+                }
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            Some(self.bbox)
+        } else {
+            None
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::grfx::ray::Ray;
 use crate::grfx::vector::Vec3D;
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 #[derive(Debug, Clone)]
 pub struct Camera {
@@ -12,6 +12,8 @@ pub struct Camera {
     u: Vec3D,
     v: Vec3D,
     w: Vec3D,
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
@@ -45,24 +47,48 @@ impl Camera {
             v,
             u,
             w,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
-    pub fn ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+
+    ///
+    /// Keep the shutter open from time open to close, rays are sent at random times in between
+    /// so that moving objects get blurred. By default the shutter opens and closes at 0.
+    ///
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    /// Times at which the shutter opens and closes
+    pub fn shutter(&self) -> (f32, f32) {
+        (self.shutter_open, self.shutter_close)
+    }
+
+    /// Ray through the viewport coordinates (s, t), rng is used to sample the lens and time
+    pub fn ray(&self, s: f32, t: f32, rng: &mut dyn RngCore) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(rng);
         let offset = self.u * rd.x() + self.v * rd.y();
-        Ray::new(
+        let time = if self.shutter_close != self.shutter_open {
+            self.shutter_open + rng.gen::<f32>() * (self.shutter_close - self.shutter_open)
+        } else {
+            self.shutter_open
+        };
+        Ray::with_time(
             self.origin + offset,
             self.lower_left + s * self.horizontal + t * self.vertical - self.origin - offset,
+            time,
         )
     }
 }
 
-fn random_in_unit_disk() -> Vec3D {
+fn random_in_unit_disk(rng: &mut dyn RngCore) -> Vec3D {
     // let mut p = Vec3D::default();
-    let mut rand = rand::thread_rng();
     loop {
         let p =
-            2.0 * Vec3D::new(rand.gen::<f32>(), rand.gen::<f32>(), 0.0) - Vec3D::new(1.0, 1.0, 0.0);
+            2.0 * Vec3D::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0) - Vec3D::new(1.0, 1.0, 0.0);
         if Vec3D::dot(&p, &p) >= 1.0 {
             return p;
         }
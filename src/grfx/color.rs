@@ -2,10 +2,13 @@ use std::ops::Add;
 use std::ops::AddAssign;
 use std::ops::Div;
 use std::ops::Mul;
+use std::ops::MulAssign;
 use std::ops::Sub;
 use std::ops::SubAssign;
 
-/// RGB like color structure
+/// RGB like color structure (8 bits per channel)
+/// Used for the final pixels of an image, see Rgb for light/color calculations.
+/// Arithmetic saturates at 0 and 255.
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Color(u8, u8, u8, u8);
 
@@ -68,23 +71,31 @@ impl Color {
 impl Add for Color {
     type Output = Color;
     fn add(self, other: Color) -> Self::Output {
-        Color(self.0 + other.0, self.1 + other.1, self.2 + other.2, 255)
+        Color(
+            self.0.saturating_add(other.0),
+            self.1.saturating_add(other.1),
+            self.2.saturating_add(other.2),
+            255,
+        )
     }
 }
 /// Operator -
 impl Sub for Color {
     type Output = Color;
     fn sub(self, other: Color) -> Self::Output {
-        Color(self.0 - other.0, self.1 - other.1, self.2 - other.2, 255)
+        Color(
+            self.0.saturating_sub(other.0),
+            self.1.saturating_sub(other.1),
+            self.2.saturating_sub(other.2),
+            255,
+        )
     }
 }
 
 /// Operator  +=
 impl AddAssign for Color {
     fn add_assign(&mut self, other: Color) {
-        self.0 += other.0;
-        self.1 += other.1;
-        self.2 += other.2;
+        *self = *self + other;
         self.3 = 255; // set opasity to max
     }
 }
@@ -92,9 +103,7 @@ impl AddAssign for Color {
 /// Operator  -=
 impl SubAssign for Color {
     fn sub_assign(&mut self, other: Color) {
-        self.0 -= other.0;
-        self.1 -= other.1;
-        self.2 -= other.2;
+        *self = *self - other;
         self.3 = 255; // set opasity to max
     }
 }
@@ -141,3 +150,129 @@ impl Div<f32> for Color {
         ) // alpha/opacity as max
     }
 }
+
+///
+/// Linear floating point RGB color.
+///
+/// Used for all light calculations (albedos, attenuation, radiance) so that values
+/// don't get truncated at every bounce and can go above 1.0 (i.e. bright lights).
+/// Only quantized to an 8 bit Color when writing the final image, see to_color().
+///
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
+pub struct Rgb(f32, f32, f32);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Rgb = Rgb(1.0, 1.0, 1.0);
+
+    /// Create a new color
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        Self(r, g, b)
+    }
+    /// Retrieve red component
+    pub fn r(&self) -> f32 {
+        self.0
+    }
+    /// Retrieve green component
+    pub fn g(&self) -> f32 {
+        self.1
+    }
+    /// Retrieve blue component
+    pub fn b(&self) -> f32 {
+        self.2
+    }
+    /// Largest of the three components
+    pub fn max_component(&self) -> f32 {
+        self.0.max(self.1).max(self.2)
+    }
+    /// Determine whether the color is black (carries no light)
+    pub fn is_black(&self) -> bool {
+        self.0 == 0.0 && self.1 == 0.0 && self.2 == 0.0
+    }
+    ///
+    /// Quantize to an 8 bit color.
+    /// Each component is clamped to [0, 1] and gamma encoded (value^(1/gamma)).
+    ///
+    pub fn to_color(&self, gamma: f32) -> Color {
+        let encode = |channel: f32| (channel.clamp(0.0, 1.0).powf(1.0 / gamma) * 255.99) as u8;
+        Color::rgb(encode(self.0), encode(self.1), encode(self.2))
+    }
+}
+
+/// Conversion from an 8 bit color, each channel is mapped to [0, 1] without gamma decoding
+impl From<Color> for Rgb {
+    fn from(color: Color) -> Self {
+        Rgb(
+            color.r() as f32 / 255.0,
+            color.g() as f32 / 255.0,
+            color.b() as f32 / 255.0,
+        )
+    }
+}
+
+/// Operator +
+impl Add for Rgb {
+    type Output = Rgb;
+    fn add(self, other: Rgb) -> Self::Output {
+        Rgb(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+/// Operator -
+impl Sub for Rgb {
+    type Output = Rgb;
+    fn sub(self, other: Rgb) -> Self::Output {
+        Rgb(self.0 - other.0, self.1 - other.1, self.2 - other.2)
+    }
+}
+
+/// Operator  +=
+impl AddAssign for Rgb {
+    fn add_assign(&mut self, other: Rgb) {
+        self.0 += other.0;
+        self.1 += other.1;
+        self.2 += other.2;
+    }
+}
+
+/// Operator  * (color * color) component wise, i.e. light filtered by a surface
+impl Mul for Rgb {
+    type Output = Rgb;
+    fn mul(self, other: Rgb) -> Self::Output {
+        Rgb(self.0 * other.0, self.1 * other.1, self.2 * other.2)
+    }
+}
+
+/// Operator  *= (color *= color) component wise
+impl MulAssign for Rgb {
+    fn mul_assign(&mut self, other: Rgb) {
+        self.0 *= other.0;
+        self.1 *= other.1;
+        self.2 *= other.2;
+    }
+}
+
+/// Operator  * (color * number)
+impl Mul<f32> for Rgb {
+    type Output = Rgb;
+    fn mul(self, scalar: f32) -> Self::Output {
+        Rgb(self.0 * scalar, self.1 * scalar, self.2 * scalar)
+    }
+}
+
+/// Operator  * (number * color)
+impl Mul<Rgb> for f32 {
+    type Output = Rgb;
+    fn mul(self, color: Rgb) -> Self::Output {
+        color * self
+    }
+}
+
+/// Operator  / (color / number)
+impl Div<f32> for Rgb {
+    type Output = Rgb;
+    fn div(self, scalar: f32) -> Self::Output {
+        assert_ne!(scalar, 0.0);
+        Rgb(self.0 / scalar, self.1 / scalar, self.2 / scalar)
+    }
+}
@@ -1,7 +1,9 @@
-use crate::grfx::color::Color;
+use crate::grfx::color::{Color, Rgb};
 use pixels::{Pixels, SurfaceTexture};
 use std::fs::File;
-use std::io::Write;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
 use winit::dpi::LogicalSize;
 use winit::event::{Event, WindowEvent};
 use winit::event_loop::ControlFlow;
@@ -22,12 +24,128 @@ impl Image {
     ///
     pub fn from_colors(width: u32, height: u32, colors: Vec<Color>) -> Self {
         Self {
-            width: width,
-            height: height,
+            width,
+            height,
             pixels: colors,
         }
     }
 
+    ///
+    /// Read an image from a file, PNG or PPM (P3 or P6) detected from the file contents
+    ///
+    pub fn read_image(filename: &str) -> Result<Self, io::Error> {
+        Self::decode(BufReader::new(File::open(filename)?))
+    }
+
+    ///
+    /// Read an image from any reader, PNG or PPM (P3 or P6) detected from the contents
+    ///
+    pub fn decode<R: Read>(mut reader: R) -> Result<Self, io::Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Self::decode_png(&data)
+        } else if data.starts_with(b"P3") || data.starts_with(b"P6") {
+            Self::decode_ppm(&data)
+        } else {
+            Err(invalid_data("Unknown image format".to_string()))
+        }
+    }
+
+    /// Retrieve the image width
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Retrieve the image height
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Retrieve the color of the pixel at (x, y), (0, 0) being the top left corner
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        assert!(x < self.width && y < self.height);
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    ///
+    /// Portable Pixel Map, ASCII (P3) or binary (P6).
+    /// Header: magic, width, height and max value separated by white space, '#' starts a comment.
+    ///
+    fn decode_ppm(data: &[u8]) -> Result<Self, io::Error> {
+        let binary = data.starts_with(b"P6");
+        let mut position = 2;
+        let mut header = [0u32; 3];
+        for value in header.iter_mut() {
+            *value = ppm_number(data, &mut position)?;
+        }
+        let [width, height, max] = header;
+        if max == 0 || max > 65535 {
+            return Err(invalid_data(format!("Invalid PPM max value {}", max)));
+        }
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| invalid_data("Invalid PPM size".to_string()))?;
+        let mut samples = Vec::with_capacity(count.min(data.len()));
+        if binary {
+            // a single white space separates the header from the data
+            position += 1;
+            let size = if max < 256 { 1 } else { 2 };
+            let end = count
+                .checked_mul(size)
+                .and_then(|length| length.checked_add(position))
+                .ok_or_else(|| invalid_data("Invalid PPM size".to_string()))?;
+            let bytes = data
+                .get(position..end)
+                .ok_or_else(|| invalid_data("Truncated PPM data".to_string()))?;
+            for sample in bytes.chunks(size) {
+                samples.push(sample.iter().fold(0, |acc, b| acc * 256 + *b as u32));
+            }
+        } else {
+            for _ in 0..count {
+                samples.push(ppm_number(data, &mut position)?);
+            }
+        }
+        let scale = |sample: u32| (sample.min(max) * 255 / max) as u8;
+        let pixels = samples
+            .chunks(3)
+            .map(|rgb| Color::rgb(scale(rgb[0]), scale(rgb[1]), scale(rgb[2])))
+            .collect();
+        Ok(Self::from_colors(width, height, pixels))
+    }
+
+    ///
+    /// Portable Network Graphics, any color type/depth is converted to 8 bit RGBA
+    ///
+    fn decode_png(data: &[u8]) -> Result<Self, io::Error> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(png_decoding_error)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(png_decoding_error)?;
+        let bytes = &buffer[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgb => bytes
+                .chunks(3)
+                .map(|p| Color::rgb(p[0], p[1], p[2]))
+                .collect(),
+            png::ColorType::Rgba => bytes
+                .chunks(4)
+                .map(|p| Color::rgba(p[0], p[1], p[2], p[3]))
+                .collect(),
+            png::ColorType::Grayscale => bytes.iter().map(|g| Color::rgb(*g, *g, *g)).collect(),
+            png::ColorType::GrayscaleAlpha => bytes
+                .chunks(2)
+                .map(|p| Color::rgba(p[0], p[0], p[0], p[1]))
+                .collect(),
+            png::ColorType::Indexed => {
+                return Err(invalid_data("Unexpected indexed PNG data".to_string()))
+            }
+        };
+        Ok(Self::from_colors(info.width, info.height, pixels))
+    }
+
     ///
     /// Show image of the screen using winit and pixels
     ///
@@ -73,19 +191,361 @@ impl Image {
 
     ///
     /// Write image to a file with the name/location of filename
-    /// Returns () upon success or io error in case of ailure.
-    ///
-    /// Export format Portable Pixel Map (PPM)
-    pub fn write_image(&self, filename: &str) -> Result<(), std::io::Error> {
-        let mut file = File::create(filename).expect("Could not create file");
-        file.write_fmt(format_args!(
-            "{}\n{} {} \n255\n",
-            "P3", self.width, self.height
-        ))
-        .unwrap();
-        for color in &self.pixels {
-            file.write_all(color.to_string().as_bytes())?;
+    /// The format is chosen from the file extension (.png or .ppm), see ImageFormat.
+    /// Returns () upon success or io error in case of failure.
+    ///
+    pub fn write_image(&self, filename: &str) -> Result<(), io::Error> {
+        match ImageFormat::from_filename(filename) {
+            Some(format) => self.write_image_as(filename, format),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown image format for file {}", filename),
+            )),
+        }
+    }
+
+    ///
+    /// Write image to a file with the name/location of filename in the format given
+    /// Returns () upon success or io error in case of failure.
+    ///
+    pub fn write_image_as(&self, filename: &str, format: ImageFormat) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(filename)?);
+        self.encode(file, format)
+    }
+
+    ///
+    /// Write the image to any writer in the format given
+    ///
+    pub fn encode<W: Write>(&self, mut writer: W, format: ImageFormat) -> Result<(), io::Error> {
+        match format {
+            ImageFormat::PpmAscii => {
+                write!(writer, "P3\n{} {}\n255\n", self.width, self.height)?;
+                for color in &self.pixels {
+                    writer.write_all(color.to_string().as_bytes())?;
+                }
+            }
+            ImageFormat::Ppm => {
+                write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+                let bytes: Vec<u8> = self
+                    .pixels
+                    .iter()
+                    .flat_map(|color| [color.r(), color.g(), color.b()])
+                    .collect();
+                writer.write_all(&bytes)?;
+            }
+            ImageFormat::Pfm | ImageFormat::Exr => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "8 bit images can't be written in a floating point format, use HdrImage",
+                ))
+            }
+            ImageFormat::Png => {
+                // only keep the alpha channel when there is some transparency
+                let opaque = self.pixels.iter().all(|color| color.alpha() == 255);
+                let mut encoder = png::Encoder::new(&mut writer, self.width, self.height);
+                encoder.set_depth(png::BitDepth::Eight);
+                let bytes: Vec<u8> = if opaque {
+                    encoder.set_color(png::ColorType::Rgb);
+                    self.pixels
+                        .iter()
+                        .flat_map(|color| [color.r(), color.g(), color.b()])
+                        .collect()
+                } else {
+                    encoder.set_color(png::ColorType::Rgba);
+                    self.pixels
+                        .iter()
+                        .flat_map(|color| color.as_bytes())
+                        .collect()
+                };
+                let mut png_writer = encoder.write_header().map_err(png_error)?;
+                png_writer.write_image_data(&bytes).map_err(png_error)?;
+                png_writer.finish().map_err(png_error)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+///
+/// High dynamic range image, each pixel is a linear floating point color.
+///
+/// Keeps values above 1.0 (lights, bright sky) so renders can be tone mapped
+/// or graded afterwards. Written as Portable Float Map or OpenEXR.
+///
+pub struct HdrImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgb>,
+}
+
+impl HdrImage {
+    ///
+    /// create a new image from a vector of (row by row, top first) colors with the given dimensions
+    ///
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Rgb>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Retrieve the image width
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Retrieve the image height
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Retrieve the color of the pixel at (x, y), (0, 0) being the top left corner
+    pub fn get(&self, x: u32, y: u32) -> Rgb {
+        assert!(x < self.width && y < self.height);
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    ///
+    /// Quantize to an 8 bit image, see Rgb::to_color
+    ///
+    pub fn to_image(&self, gamma: f32) -> Image {
+        let colors = self
+            .pixels
+            .iter()
+            .map(|color| color.to_color(gamma))
+            .collect();
+        Image::from_colors(self.width, self.height, colors)
+    }
+
+    ///
+    /// Write image to a file with the name/location of filename
+    /// The format is chosen from the file extension (.pfm or .exr), see ImageFormat.
+    ///
+    pub fn write_image(&self, filename: &str) -> Result<(), io::Error> {
+        match ImageFormat::from_filename(filename) {
+            Some(format) => self.write_image_as(filename, format),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown image format for file {}", filename),
+            )),
+        }
+    }
+
+    ///
+    /// Write image to a file with the name/location of filename in the format given
+    ///
+    pub fn write_image_as(&self, filename: &str, format: ImageFormat) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(filename)?);
+        self.encode(file, format)
+    }
+
+    ///
+    /// Write the image to any writer in the (floating point) format given
+    ///
+    pub fn encode<W: Write>(&self, mut writer: W, format: ImageFormat) -> Result<(), io::Error> {
+        match format {
+            ImageFormat::Pfm => self.encode_pfm(&mut writer)?,
+            ImageFormat::Exr => self.encode_exr(&mut writer)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "HDR images can only be written as PFM or EXR, see to_image()",
+                ))
+            }
+        }
+        writer.flush()
+    }
+
+    ///
+    /// Portable Float Map: text header followed by little endian floats,
+    /// a negative scale means little endian. Rows are stored bottom to top.
+    ///
+    fn encode_pfm<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 12);
+        for row in self.pixels.chunks(self.width as usize).rev() {
+            for color in row {
+                for channel in &[color.r(), color.g(), color.b()] {
+                    bytes.extend_from_slice(&channel.to_le_bytes());
+                }
+            }
+        }
+        writer.write_all(&bytes)
+    }
+
+    ///
+    /// OpenEXR single part scan line file, 32 bit float channels and no compression.
+    /// Layout: magic/version, header attributes, offset of each line, then the lines
+    /// (y, size, and each channel in alphabetical order: B, G, R).
+    ///
+    fn encode_exr<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        const FLOAT: i32 = 2;
+        let mut header = Vec::new();
+        header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let mut channels = Vec::new();
+        for name in &[b"B", b"G", b"R"] {
+            channels.extend_from_slice(*name);
+            channels.push(0);
+            channels.extend_from_slice(&FLOAT.to_le_bytes());
+            channels.extend_from_slice(&[0, 0, 0, 0]); // linear flag and reserved
+            channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        channels.push(0);
+        exr_attribute(&mut header, "channels", "chlist", &channels);
+        exr_attribute(&mut header, "compression", "compression", &[0]);
+        let mut window = Vec::new();
+        for value in &[0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+        exr_attribute(&mut header, "dataWindow", "box2i", &window);
+        exr_attribute(&mut header, "displayWindow", "box2i", &window);
+        exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        exr_attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        exr_attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        header.push(0);
+        writer.write_all(&header)?;
+
+        // one line per block, each block is y + size + the channel data
+        let line_size = 3 * 4 * self.width as u64;
+        let first_line = header.len() as u64 + 8 * self.height as u64;
+        let mut offsets = Vec::with_capacity(8 * self.height as usize);
+        for y in 0..self.height as u64 {
+            offsets.extend_from_slice(&(first_line + y * (8 + line_size)).to_le_bytes());
+        }
+        writer.write_all(&offsets)?;
+
+        let mut line = Vec::with_capacity(8 + line_size as usize);
+        for (y, row) in self.pixels.chunks(self.width as usize).enumerate() {
+            line.clear();
+            line.extend_from_slice(&(y as i32).to_le_bytes());
+            line.extend_from_slice(&(line_size as i32).to_le_bytes());
+            for channel in &[Rgb::b, Rgb::g, Rgb::r] {
+                for color in row {
+                    line.extend_from_slice(&channel(color).to_le_bytes());
+                }
+            }
+            writer.write_all(&line)?;
         }
         Ok(())
     }
 }
+
+/// Append an OpenEXR header attribute: name, type name, size and value
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+///
+/// File formats images can be written in
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    /// Portable Network Graphics, 8 bit RGB (or RGBA when some pixels aren't opaque)
+    Png,
+    /// Binary Portable Pixel Map (P6)
+    Ppm,
+    /// ASCII Portable Pixel Map (P3), readable but huge
+    PpmAscii,
+    /// Portable Float Map, linear 32 bit float RGB (HdrImage only)
+    Pfm,
+    /// OpenEXR, linear 32 bit float RGB without compression (HdrImage only)
+    Exr,
+}
+
+impl ImageFormat {
+    ///
+    /// Format matching the extension of the file name given (.png, .ppm (binary), .pfm or .exr)
+    ///
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let extension = Path::new(filename).extension()?.to_str()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Format from its name: png, ppm, ppm-ascii, pfm or exr
+    ///
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "ppm-ascii" => Some(ImageFormat::PpmAscii),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    /// Determine whether the format stores high dynamic range (floating point) colors
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, ImageFormat::Pfm | ImageFormat::Exr)
+    }
+}
+
+/// Read the next (white space separated) number of a PPM file, skipping comments
+fn ppm_number(data: &[u8], position: &mut usize) -> Result<u32, io::Error> {
+    loop {
+        match data.get(*position) {
+            Some(b'#') => {
+                while !matches!(data.get(*position), Some(b'\n') | None) {
+                    *position += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            _ => break,
+        }
+    }
+    let start = *position;
+    while matches!(data.get(*position), Some(byte) if byte.is_ascii_digit()) {
+        *position += 1;
+    }
+    std::str::from_utf8(&data[start..*position])
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| invalid_data(format!("Invalid PPM number at byte {}", start)))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Turn png decoding failures into io errors
+fn png_decoding_error(error: png::DecodingError) -> io::Error {
+    match error {
+        png::DecodingError::IoError(error) => error,
+        error => invalid_data(error.to_string()),
+    }
+}
+
+/// Turn png encoding failures into io errors
+fn png_error(error: png::EncodingError) -> io::Error {
+    match error {
+        png::EncodingError::IoError(error) => error,
+        error => io::Error::other(error),
+    }
+}
@@ -1,9 +1,12 @@
 use crate::grfx::vector::Vec3D;
 use crate::grfx::ray::Ray;
-use rand::Rng;
+use rand::{Rng, RngCore};
 use crate::grfx::shape::HitRecord;
-use crate::grfx::color::Color;
-use crate::grfx::color;
+use crate::grfx::color::Rgb;
+use crate::grfx::texture::{SolidColor, Texture};
+use crate::grfx::sampling;
+use std::fmt;
+use std::sync::Arc;
 
 ///
 ///  Material trait
@@ -13,44 +16,97 @@ use crate::grfx::color;
 ///  Or how rays will interact with a surface.
 /// 
 /// to implement this trait define the scatter method:
-///     pub fn scatter(&self, ray : &Ray, records : &HitRecord, attenuation : &Vec3D, scattered : &Ray) -> bool; 
+///     pub fn scatter(&self, ray : &Ray, records : &HitRecord, attenuation : &mut Rgb, scattered : &Ray, rng : &mut dyn RngCore) -> bool; 
 ///     ray->
 ///     records->
-///     attenuation ->
+///     attenuation -> how much of each color channel the scattered ray keeps (linear, 1.0 = all)
 ///     scattered -> calculated ray.
+///     rng -> random number generator used for all sampling (keeps renders reproducible)
 /// 
-pub trait Material {
-    fn scatter(&self, ray : &Ray, records : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool; 
+/// Materials which give off light (see DiffuseLight) also define emitted:
+///     fn emitted(&self, u : f32, v : f32, point : &Vec3D) -> Rgb;
+///     (u, v) -> surface coordinates of the point hit (see HitRecord)
+///     point -> point hit
+/// 
+/// Materials scattering in every direction (not mirrors nor glass) also define, so that the renderer
+/// can aim rays at the lights (next event estimation):
+///     fn scattering_pdf(&self, ray : &Ray, record : &HitRecord, scattered : &Ray) -> f32;
+///         probability density (per solid angle) of scatter picking the scattered direction
+///     fn eval(&self, ray : &Ray, record : &HitRecord, scattered : &Ray) -> Rgb;
+///         BSDF times the cosine of the scattered direction, the light reflected along ray
+///         for a unit of light coming from the scattered direction
+/// scatter returns eval / scattering_pdf as attenuation. Materials keeping the default 0 pdf
+/// are treated as specular.
+/// 
+/// Materials are shared between render threads, hence Send + Sync.
+/// 
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray : &Ray, records : &HitRecord, attenuation : &mut Rgb, scattered : &mut Ray, rng : &mut dyn RngCore) -> bool; 
+    fn emitted(&self, _u : f32, _v : f32, _point : &Vec3D) -> Rgb {
+        Rgb::BLACK
+    }
+    fn scattering_pdf(&self, _ray : &Ray, _record : &HitRecord, _scattered : &Ray) -> f32 {
+        0.0
+    }
+    fn eval(&self, _ray : &Ray, _record : &HitRecord, _scattered : &Ray) -> Rgb {
+        Rgb::BLACK
+    }
 }
-#[derive(Debug)]
 pub struct Lambertian {
-    albedo : Color,
+    albedo : Arc<dyn Texture>,
 }
 
 /// 
 /// Material implementation for lambertian reflectance.
 /// which defines a matte surface. See wiki link:
 /// https://en.wikipedia.org/wiki/Lambertian_reflectance
+/// The albedo is either a color or a texture.
 /// 
 impl Lambertian {
-    pub fn new(albedo :Color) -> Self {Self{albedo}}
+    pub fn new(albedo :Rgb) -> Self {Self::textured(Arc::new(SolidColor::new(albedo)))}
+    pub fn textured(albedo : Arc<dyn Texture>) -> Self {Self{albedo}}
+}
+
+// textures aren't Debug, the albedo is left out
+impl fmt::Debug for Lambertian {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lambertian").finish()
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray : &Ray, record : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool {
-        let target = record.point + record.normal + random_in_unit_sphere();
-        *scattered = Ray::new(record.point, target - record.point);
-        *attenuation = self.albedo.clone();
+    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Rgb, scattered : &mut Ray, rng : &mut dyn RngCore) -> bool {
+        let direction = facing_normal(ray, record).local_to_world(&sampling::cosine_hemisphere(rng));
+        *scattered = Ray::with_time(record.point, direction, ray.time());
+        *attenuation = self.albedo.value(record.u, record.v, &record.point);
         // println!{"Att:{:?}",self.albedo};
         true
     }
+    fn scattering_pdf(&self, ray : &Ray, record : &HitRecord, scattered : &Ray) -> f32 {
+        let cosine = Vec3D::dot(&facing_normal(ray, record), &scattered.direction().unit_vector());
+        sampling::cosine_hemisphere_pdf(cosine)
+    }
+    fn eval(&self, ray : &Ray, record : &HitRecord, scattered : &Ray) -> Rgb {
+        self.albedo.value(record.u, record.v, &record.point) * self.scattering_pdf(ray, record, scattered)
+    }
 }
 
-fn random_in_unit_sphere() -> Vec3D {
+///
+/// Surface normal on the side the ray comes from.
+/// Shapes like triangles can be hit from the back, where their normal points away from the ray.
+///
+fn facing_normal(ray : &Ray, record : &HitRecord) -> Vec3D {
+    if Vec3D::dot(&ray.direction(), &record.normal) > 0.0 {
+        -record.normal
+    } else {
+        record.normal
+    }
+}
+
+fn random_in_unit_sphere(rng : &mut dyn RngCore) -> Vec3D {
     let mut vector : Vec3D;
-    let mut rand = rand::thread_rng();
     loop {
-        vector = 2.0 * Vec3D::new(rand.gen::<f32>(),rand.gen::<f32>(),rand.gen::<f32>() ) - Vec3D::new(1.0, 1.0, 1.0);
+        vector = 2.0 * Vec3D::new(rng.gen::<f32>(),rng.gen::<f32>(),rng.gen::<f32>() ) - Vec3D::new(1.0, 1.0, 1.0);
         if vector.squared_length() < 1.0 {
             break;
         }
@@ -64,27 +120,33 @@ fn random_in_unit_sphere() -> Vec3D {
 ///  Material reflection struct for metals
 /// 
 /// 
-#[derive(Debug)]
 pub struct Metal {
-    albedo : Color,
+    albedo : Arc<dyn Texture>,
     fuzz : Option<f32>
 }
 impl Metal {
-    pub fn new(albedo :Color, fuzz: Option<f32>) -> Self { Self{albedo, fuzz}}
+    pub fn new(albedo :Rgb, fuzz: Option<f32>) -> Self { Self::textured(Arc::new(SolidColor::new(albedo)), fuzz)}
+    pub fn textured(albedo : Arc<dyn Texture>, fuzz: Option<f32>) -> Self { Self{albedo, fuzz}}
+}
+impl fmt::Debug for Metal {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metal").field("fuzz", &self.fuzz).finish()
+    }
 }
 impl Material for Metal {
-    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool {
+    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Rgb, scattered : &mut Ray, rng : &mut dyn RngCore) -> bool {
         // DEfault fuzz index is 1.0
         let fuzz = match self.fuzz {
             Some(value) => value.min(1.0),
             _ => 1.0,
         };
-        let reflected = reflect(&(ray.direction().unit_vector()), &record.normal); 
+        let normal = facing_normal(ray, record);
+        let reflected = reflect(&(ray.direction().unit_vector()), &normal); 
         //record.point + record.normal + random_in_unit_sphere();
-        *scattered = Ray::new(record.point, reflected + random_in_unit_sphere() * fuzz );
-        *attenuation = self.albedo.clone();
+        *scattered = Ray::with_time(record.point, reflected + random_in_unit_sphere(rng) * fuzz, ray.time());
+        *attenuation = self.albedo.value(record.u, record.v, &record.point);
         // println!{"Att:{:?}",self.albedo};
-        Vec3D::dot(&scattered.direction(), &record.normal) > 0.0
+        Vec3D::dot(&scattered.direction(), &normal) > 0.0
     }
 }
 
@@ -98,57 +160,327 @@ fn schlick(cosine: f32 , ref_idx : f32) -> f32 {
     r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
 }
 
-fn refract( v  : &Vec3D, n : &Vec3D, ni_over_nt: f32, refracted: &mut Vec3D) -> bool {
-    let uv = v.unit_vector();
-    let dt = Vec3D::dot(&uv, &n);
-    let discriminant = 1.0 - (ni_over_nt * ni_over_nt  * (1.0 - dt * dt));
-    if discriminant > 0.0 {
-        *refracted = (ni_over_nt * (uv - dt * n)) - (n * discriminant.sqrt());
-        return true;
+///
+/// Wavelength dependence of the index of refraction, which splits white light into its colors
+/// (wavelengths in micrometers in the formulas):
+///     Cauchy -> n = a + b / λ², i.e. a = 1.5046, b = 0.0042 for BK7 glass
+///     Sellmeier -> n² = 1 + Σ bᵢ λ² / (λ² - cᵢ), the form used by glass catalogs
+/// 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    Cauchy { a : f32, b : f32 },
+    Sellmeier { b : [f32; 3], c : [f32; 3] },
+}
+
+impl Dispersion {
+    /// Index of refraction for a wavelength given in nanometers
+    pub fn ior(&self, wavelength : f32) -> f32 {
+        let squared = (wavelength / 1000.0) * (wavelength / 1000.0);
+        match self {
+            Dispersion::Cauchy{a, b} => a + b / squared,
+            Dispersion::Sellmeier{b, c} => {
+                let sum : f32 = (0..3).map(|i| b[i] * squared / (squared - c[i])).sum();
+                (1.0 + sum).max(1.0).sqrt()
+            }
+        }
     }
-    false
 }
+
+/// Wavelengths (nm) standing for the red, green and blue channels when refracting dispersive materials (CIE RGB primaries)
+const CHANNEL_WAVELENGTHS : [f32; 3] = [700.0, 546.1, 435.8];
+
+/// Wavelength (nm) at which indices of refraction are usually given (helium d line)
+const REFERENCE_WAVELENGTH : f32 = 587.6;
+
+///
+/// Transparent material (glass, water, diamond) reflecting or refracting rays
+/// following the Fresnel equations (Schlick's approximation).
+///     ref_idx -> index of refraction of the material
+///     exterior_idx -> index of refraction of the medium around the object, 1 (air) unless
+///                     given with with_exterior_ior, i.e. 1.33 for glass in water
+///     absorption -> tinted materials absorb light going through them (Beer-Lambert law), see with_tint
+///     dispersion -> optional wavelength dependent index of refraction, see Dispersion.
+///                   Uses the wavelength of the ray when it has one (spectral rendering), otherwise
+///                   one of the red, green or blue channels is picked at random and its wavelength
+///                   is given to the scattered ray so that the rest of the path keeps that channel.
+///     thickness -> thin walled materials (windows, bubbles) are sheets of that thickness:
+///                  rays going through aren't bent and the object has no inside.
+/// 
 pub struct Dialectric {
-    ref_idx : f32
+    ref_idx : f32,
+    exterior_idx : f32,
+    absorption : Rgb,
+    dispersion : Option<Dispersion>,
+    thickness : Option<f32>,
 }
 
 impl Dialectric {
     pub fn new(ref_idx: f32)->Self {
-        Self{ref_idx}
+        Self{ref_idx, exterior_idx : 1.0, absorption : Rgb::BLACK, dispersion : None, thickness : None}
+    }
+    /// Material with a wavelength dependent index of refraction, ior() gives the index at 587.6 nm
+    pub fn dispersive(dispersion : Dispersion) -> Self {
+        let mut material = Self::new(dispersion.ior(REFERENCE_WAVELENGTH));
+        material.dispersion = Some(dispersion);
+        material
+    }
+    pub fn with_exterior_ior(mut self, exterior_idx : f32) -> Self {
+        self.exterior_idx = exterior_idx;
+        self
+    }
+    /// Light going through the material keeps the given color after travelling the given distance inside
+    pub fn with_tint(mut self, color : Rgb, distance : f32) -> Self {
+        let coefficient = |channel : f32| -channel.clamp(1e-6, 1.0).ln() / distance.max(1e-6);
+        self.absorption = Rgb::new(coefficient(color.r()), coefficient(color.g()), coefficient(color.b()));
+        self
+    }
+    /// Make the material a thin sheet of the given thickness
+    pub fn thin_walled(mut self, thickness : f32) -> Self {
+        self.thickness = Some(thickness.max(0.0));
+        self
+    }
+    pub fn ior(&self) -> f32 {self.ref_idx}
+    pub fn exterior_ior(&self) -> f32 {self.exterior_idx}
+    /// Absorption coefficients per unit of distance
+    pub fn absorption(&self) -> Rgb {self.absorption}
+    pub fn dispersion(&self) -> Option<Dispersion> {self.dispersion}
+    pub fn thickness(&self) -> Option<f32> {self.thickness}
+
+    /// Fraction of the light kept after travelling distance inside the material
+    fn transmittance(&self, distance : f32) -> Rgb {
+        let keep = |coefficient : f32| (-coefficient * distance).exp();
+        Rgb::new(keep(self.absorption.r()), keep(self.absorption.g()), keep(self.absorption.b()))
     }
 }
+
 impl Material for Dialectric {
-    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Color, scattered : &mut Ray) -> bool {
-        let outward_normal;
-        let ni_over_nt;
-        let mut rand = rand::thread_rng();
-        let reflected = reflect(&ray.direction(), &record.normal);
-        let mut refracted = Vec3D::default();
-        let reflect_prob : f32;
-        let cosine : f32;
-        *attenuation = color::WHITE;  // white default for transparent glossy reflection 
-        if Vec3D::dot(&ray.direction(), &record.normal) > 0.0 {
-            outward_normal = -record.normal.clone();
-            ni_over_nt = self.ref_idx;
-            cosine = self.ref_idx * Vec3D::dot(&ray.direction(), &record.normal) / ray.direction().length();
+    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Rgb, scattered : &mut Ray, rng : &mut dyn RngCore) -> bool {
+        let unit = ray.direction().unit_vector();
+        let mut ref_idx = self.ref_idx;
+        let mut wavelength = ray.wavelength();
+        *attenuation = Rgb::WHITE;  // white default for transparent glossy reflection 
+        if let Some(dispersion) = &self.dispersion {
+            if wavelength.is_none() {
+                // the channel picked goes on for the rest of the path (carried as the wavelength
+                // of the ray), 3 times brighter so that colors average out
+                let channel = rng.gen_range(0..3);
+                let mut weights = [0.0; 3];
+                weights[channel] = 3.0;
+                *attenuation = Rgb::new(weights[0], weights[1], weights[2]);
+                wavelength = Some(CHANNEL_WAVELENGTHS[channel]);
+            }
+            ref_idx = dispersion.ior(wavelength.unwrap_or(REFERENCE_WAVELENGTH));
+        }
+        let inside = Vec3D::dot(&unit, &record.normal) > 0.0 && self.thickness.is_none();
+        let (normal, ni, nt) = if inside {
+            // the ray went through the material to get here
+            *attenuation *= self.transmittance(record.t * ray.direction().length());
+            (-record.normal, ref_idx, self.exterior_idx)
+        } else {
+            (facing_normal(ray, record), self.exterior_idx, ref_idx)
+        };
+        let ni_over_nt = ni / nt;
+        let cos_in = (-Vec3D::dot(&unit, &normal)).min(1.0);
+        let sin2_out = ni_over_nt * ni_over_nt * (1.0 - cos_in * cos_in);
+        let cos_out = (1.0 - sin2_out).max(0.0).sqrt();
+        let reflect_prob = if sin2_out >= 1.0 {
+            1.0     // total internal reflection
         } else {
-            outward_normal = record.normal.clone();
-            ni_over_nt = 1.0 / self.ref_idx;
-            cosine = - Vec3D::dot(&ray.direction(), &record.normal) / ray.direction().length();
+            // Schlick's approximation needs the angle on the less dense side
+            let reflectance = schlick(if ni > nt {cos_out} else {cos_in}, ni_over_nt);
+            match self.thickness {
+                // light bounces back and forth inside the sheet, adding up the reflections of both sides
+                Some(_) => 2.0 * reflectance / (1.0 + reflectance),
+                None => reflectance,
+            }
+        };
+        let direction = if rng.gen::<f32>() < reflect_prob {
+            reflect(&unit, &normal)
+        } else if let Some(thickness) = self.thickness {
+            // the sheet shifts the ray a tiny bit but doesn't change its direction
+            *attenuation *= self.transmittance(thickness / cos_out.max(1e-3));
+            unit
+        } else {
+            ni_over_nt * unit + (ni_over_nt * cos_in - cos_out) * normal
+        };
+        *scattered = Ray::with_time(record.point, direction, ray.time()).with_wavelength(wavelength);
+        true
+    }
+}
+
+///
+/// Phase function of participating media (see ConstantMedium): light is scattered
+/// in a uniformly random direction, whatever the direction it came from.
+/// The albedo is either a color or a texture.
+/// 
+pub struct Isotropic {
+    albedo : Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo :Rgb) -> Self {Self::textured(Arc::new(SolidColor::new(albedo)))}
+    pub fn textured(albedo : Arc<dyn Texture>) -> Self {Self{albedo}}
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Rgb, scattered : &mut Ray, rng : &mut dyn RngCore) -> bool {
+        *scattered = Ray::with_time(record.point, sampling::uniform_sphere(rng), ray.time());
+        *attenuation = self.albedo.value(record.u, record.v, &record.point);
+        true
+    }
+    fn scattering_pdf(&self, _ray : &Ray, _record : &HitRecord, _scattered : &Ray) -> f32 {
+        sampling::uniform_sphere_pdf()
+    }
+    fn eval(&self, _ray : &Ray, record : &HitRecord, _scattered : &Ray) -> Rgb {
+        self.albedo.value(record.u, record.v, &record.point) * sampling::uniform_sphere_pdf()
+    }
+}
+
+///
+/// Physically based material following the glTF metallic/roughness model, so that values from
+/// modelling tools map directly:
+///     base_color -> albedo of dielectrics, reflection color of metals (color or texture)
+///     metallic -> 0 for dielectrics (plastic, wood...), 1 for metals, in between for blends
+///     roughness -> 0 for a mirror like finish to 1 for a very rough one
+///     specular -> reflectivity of dielectrics, 0.5 (the default) reflects 4% at normal incidence
+///
+/// Reflections use microfacets following the GGX distribution with Smith masking and shadowing
+/// and Schlick's Fresnel, light not reflected by dielectrics is diffused (Lambertian).
+/// 
+pub struct Principled {
+    base_color : Arc<dyn Texture>,
+    metallic : f32,
+    roughness : f32,
+    specular : f32,
+}
+
+impl Principled {
+    pub fn new(base_color :Rgb, metallic : f32, roughness : f32) -> Self {Self::textured(Arc::new(SolidColor::new(base_color)), metallic, roughness)}
+    pub fn textured(base_color : Arc<dyn Texture>, metallic : f32, roughness : f32) -> Self {
+        Self{base_color, metallic : metallic.clamp(0.0, 1.0), roughness : roughness.clamp(0.0, 1.0), specular : 0.5}
+    }
+    pub fn with_specular(mut self, specular : f32) -> Self {
+        self.specular = specular.max(0.0);
+        self
+    }
+    pub fn metallic(&self) -> f32 {self.metallic}
+    pub fn roughness(&self) -> f32 {self.roughness}
+    pub fn specular(&self) -> f32 {self.specular}
+
+    /// GGX alpha, perceptual roughness squared (kept above 0 as perfect mirrors have no density)
+    fn alpha(&self) -> f32 {
+        (self.roughness * self.roughness).max(1e-3)
+    }
+
+    /// Reflectance at normal incidence: gray for dielectrics, the base color for metals
+    fn f0(&self, base : Rgb) -> Rgb {
+        let dielectric = 0.08 * self.specular;
+        Rgb::new(dielectric, dielectric, dielectric) * (1.0 - self.metallic) + base * self.metallic
+    }
+
+    /// Probability of sampling the glossy reflection rather than the diffuse lobe, from how much light each one returns
+    fn specular_probability(&self, base : Rgb, cos_view : f32) -> f32 {
+        let fresnel = fresnel_schlick(self.f0(base), cos_view);
+        let specular = luminance(fresnel);
+        let diffuse = luminance(base * (1.0 - self.metallic)) * (1.0 - specular);
+        if specular + diffuse <= 0.0 {
+            return 1.0;
         }
+        (specular / (specular + diffuse)).clamp(0.1, 1.0)
+    }
+}
 
-        if refract(&ray.direction(), &outward_normal, ni_over_nt, &mut refracted) {
-            *scattered = Ray::new(record.point, reflected);
-            reflect_prob = schlick(cosine, self.ref_idx);
+impl Material for Principled {
+    fn scatter(&self, ray : &Ray, record : &HitRecord, attenuation : &mut Rgb, scattered : &mut Ray, rng : &mut dyn RngCore) -> bool {
+        let normal = facing_normal(ray, record);
+        let view = -ray.direction().unit_vector();
+        let base = self.base_color.value(record.u, record.v, &record.point);
+        let direction = if rng.gen::<f32>() < self.specular_probability(base, Vec3D::dot(&normal, &view)) {
+            let half = normal.local_to_world(&sampling::ggx_normal(self.alpha(), rng));
+            reflect(&-view, &half)
         } else {
-            *scattered = Ray::new(record.point, reflected);
-            reflect_prob = 1.0;
+            normal.local_to_world(&sampling::cosine_hemisphere(rng))
+        };
+        *scattered = Ray::with_time(record.point, direction, ray.time());
+        // weight by the density of both lobes together so the estimate stays unbiased
+        let pdf = self.scattering_pdf(ray, record, scattered);
+        if pdf <= 0.0 {
+            return false;
         }
-        if rand.gen::<f32>() < reflect_prob {
-            *scattered = Ray::new(record.point, reflected);
-        } else {
-            *scattered = Ray::new(record.point, refracted);
+        *attenuation = self.eval(ray, record, scattered) / pdf;
+        true
+    }
+    fn scattering_pdf(&self, ray : &Ray, record : &HitRecord, scattered : &Ray) -> f32 {
+        let normal = facing_normal(ray, record);
+        let view = -ray.direction().unit_vector();
+        let light = scattered.direction().unit_vector();
+        let (cos_view, cos_light) = (Vec3D::dot(&normal, &view), Vec3D::dot(&normal, &light));
+        if cos_light <= 0.0 || cos_view <= 0.0 {
+            return 0.0;
         }
-        return true;
+        let half = (view + light).unit_vector();
+        let base = self.base_color.value(record.u, record.v, &record.point);
+        let probability = self.specular_probability(base, cos_view);
+        // reflecting doubles the angles: the density of directions is a quarter of the one of the half vectors
+        let specular = sampling::ggx_normal_pdf(self.alpha(), Vec3D::dot(&normal, &half)) / (4.0 * Vec3D::dot(&view, &half).max(1e-6));
+        probability * specular + (1.0 - probability) * sampling::cosine_hemisphere_pdf(cos_light)
+    }
+    fn eval(&self, ray : &Ray, record : &HitRecord, scattered : &Ray) -> Rgb {
+        let normal = facing_normal(ray, record);
+        let view = -ray.direction().unit_vector();
+        let light = scattered.direction().unit_vector();
+        let (cos_view, cos_light) = (Vec3D::dot(&normal, &view), Vec3D::dot(&normal, &light));
+        if cos_light <= 0.0 || cos_view <= 0.0 {
+            return Rgb::BLACK;
+        }
+        let half = (view + light).unit_vector();
+        let base = self.base_color.value(record.u, record.v, &record.point);
+        let alpha = self.alpha();
+        let fresnel = fresnel_schlick(self.f0(base), Vec3D::dot(&view, &half));
+        let distribution = sampling::ggx_distribution(alpha, Vec3D::dot(&normal, &half));
+        let masking = smith_g1(alpha, cos_view) * smith_g1(alpha, cos_light);
+        let specular = fresnel * (distribution * masking / (4.0 * cos_view * cos_light));
+        let diffuse = (Rgb::WHITE - fresnel) * base * ((1.0 - self.metallic) / std::f32::consts::PI);
+        (specular + diffuse) * cos_light
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, per channel
+fn fresnel_schlick(f0 : Rgb, cosine : f32) -> Rgb {
+    let weight = (1.0 - cosine.clamp(0.0, 1.0)).powi(5);
+    f0 + (Rgb::WHITE - f0) * weight
+}
+
+/// Smith masking (or shadowing) for GGX, fraction of the microfacets seen from a direction with the given cosine
+fn smith_g1(alpha : f32, cosine : f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    2.0 * cosine / (cosine + (alpha2 + (1.0 - alpha2) * cosine * cosine).sqrt())
+}
+
+fn luminance(color : Rgb) -> f32 {
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+///
+/// Material which gives off light (and doesn't reflect any), turns any shape into an area light.
+/// Colors above 1.0 make brighter lights.
+/// 
+#[derive(Debug)]
+pub struct DiffuseLight {
+    emit : Rgb,
+}
+
+impl DiffuseLight {
+    pub fn new(emit : Rgb) -> Self {
+        Self{emit}
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray : &Ray, _record : &HitRecord, _attenuation : &mut Rgb, _scattered : &mut Ray, _rng : &mut dyn RngCore) -> bool {
+        false
+    }
+    fn emitted(&self, _u : f32, _v : f32, _point : &Vec3D) -> Rgb {
+        self.emit
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,120 @@
+use crate::grfx::aabb::Aabb;
+use crate::grfx::material::Material;
+use crate::grfx::ray::Ray;
+use crate::grfx::shape::{HitRecord, Hitable};
+use crate::grfx::vector::Vec3D;
+use std::sync::Arc;
+
+///
+/// Participating medium of constant density (fog, smoke, mist) filling a closed boundary shape.
+///
+/// Rays going through the medium may scatter anywhere inside it: the distance travelled before
+/// scattering is drawn from an exponential distribution, so thicker layers and higher densities
+/// scatter more light. Scattering uses the phase function material (usually Isotropic),
+/// the material of the boundary is ignored.
+///
+/// The boundary has to be convex (spheres, boxes...) since rays only enter and leave it once.
+///
+/// Hitables don't get the render's random generator, the distance is hashed from the ray
+/// instead (see hash_ray): rays with the same origin, direction, time and wavelength always
+/// scatter at the same distance. Camera and scattered rays practically never coincide,
+/// but the samples aren't independent draws from the seeded generator.
+///
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hitable>,
+    density: f32,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hitable>,
+        density: f32,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+
+    pub fn boundary(&self) -> &Arc<dyn Hitable> {
+        &self.boundary
+    }
+
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+}
+
+impl Hitable for ConstantMedium {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        // where the ray line enters and leaves the boundary, the entry can be behind the origin
+        // when the ray starts inside (i.e. after scattering in the medium)
+        let mut entry = HitRecord::new();
+        let mut exit = HitRecord::new();
+        if !self
+            .boundary
+            .hit(ray, f32::NEG_INFINITY, f32::INFINITY, &mut entry)
+            || !self
+                .boundary
+                .hit(ray, entry.t + 0.0001, f32::INFINITY, &mut exit)
+        {
+            return false;
+        }
+        let t_in = entry.t.max(min).max(0.0);
+        let t_out = exit.t.min(max);
+        if t_in >= t_out {
+            return false;
+        }
+        let speed = ray.direction().length();
+        let inside = (t_out - t_in) * speed;
+        let distance = -(1.0 - hash_ray(ray)).ln() / self.density;
+        if distance > inside {
+            return false;
+        }
+        record.t = t_in + distance / speed;
+        record.point = ray.point_at(record.t);
+        // scattering doesn't depend on a surface, any normal will do
+        record.normal = Vec3D::new(1.0, 0.0, 0.0);
+        record.u = entry.u;
+        record.v = entry.v;
+        record.material = self.phase_function.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+///
+/// Pseudo random number in [0, 1) from the ray (splitmix64 over the bits of its origin,
+/// direction, time and wavelength). Hitables don't get a random generator, this keeps the
+/// free flight distances random across rays while renders stay reproducible.
+/// The wavelength keeps the spectral samples of a path from all scattering at the same place.
+///
+fn hash_ray(ray: &Ray) -> f32 {
+    let (origin, direction) = (ray.origin(), ray.direction());
+    let values = [
+        origin.x(),
+        origin.y(),
+        origin.z(),
+        direction.x(),
+        direction.y(),
+        direction.z(),
+        ray.time(),
+    ];
+    let mut hash = 0u64;
+    // only spectral rays have a wavelength to mix in
+    for value in values.iter().chain(ray.wavelength().iter()) {
+        hash ^= value.to_bits() as u64;
+        hash = hash.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+    }
+    // 24 bits fill the f32 mantissa
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
@@ -5,3 +5,14 @@ pub mod shape;
 pub mod image;
 pub mod camera;
 pub mod material;
+pub mod aabb;
+pub mod bvh;
+pub mod render;
+pub mod scene;
+pub mod obj;
+pub mod transform;
+pub mod texture;
+pub mod perlin;
+pub mod medium;
+pub mod sampling;
+pub mod spectrum;
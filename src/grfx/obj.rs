@@ -0,0 +1,466 @@
+use crate::grfx::aabb::Aabb;
+use crate::grfx::bvh::BvhNode;
+use crate::grfx::color::Rgb;
+use crate::grfx::material::{Dialectric, DiffuseLight, Lambertian, Material, Metal, Principled};
+use crate::grfx::ray::Ray;
+use crate::grfx::scene::ParseError;
+use crate::grfx::shape::{HitList, HitRecord, Hitable, Triangle};
+use crate::grfx::vector::Vec3D;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+///
+/// Triangle mesh loaded from a Wavefront OBJ file.
+///
+/// Supports vertices (v), texture coordinates (vt), normals (vn) and polygonal faces (f)
+/// which are split into triangles. Materials come from the MTL libraries (mtllib) referenced
+/// by the file and are selected with usemtl, faces without material are light gray.
+/// MTL materials are mapped to:
+///     Dialectric -> transparent materials (d < 1 or Tr > 0), index of refraction from Ni
+///     Principled -> materials using the PBR extension, roughness from Pr and metallic from Pm
+///     Metal -> specular color (Ks) brighter than the diffuse one (Kd), fuzz from Ns
+///     DiffuseLight -> emissive color (Ke)
+///     Lambertian -> everything else, albedo from Kd
+///
+/// The triangles are stored in a bounding volume hierarchy.
+///
+pub struct Mesh {
+    triangles: BvhNode,
+    triangle_count: usize,
+}
+
+/// Error loading an OBJ or MTL file
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { file: String, error: ParseError },
+}
+
+impl Mesh {
+    ///
+    /// Read a mesh from an OBJ file, MTL libraries are looked for next to it
+    ///
+    pub fn load(filename: &str) -> Result<Self, ObjError> {
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let text = fs::read_to_string(filename)?;
+        Self::parse(&text, |library| fs::read_to_string(directory.join(library)))
+            .map_err(|error| error.in_file(filename))
+    }
+
+    ///
+    /// Parse a mesh from the text of an OBJ file.
+    /// read_library is called to get the text of each MTL library referenced by the file.
+    ///
+    pub fn parse<F>(text: &str, mut read_library: F) -> Result<Self, ObjError>
+    where
+        F: FnMut(&str) -> io::Result<String>,
+    {
+        let default_material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Rgb::new(0.8, 0.8, 0.8)));
+        let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+        let mut material = default_material;
+        let mut positions: Vec<Vec3D> = Vec::new();
+        let mut uvs: Vec<(f32, f32)> = Vec::new();
+        let mut normals: Vec<Vec3D> = Vec::new();
+        let mut triangles = HitList::new();
+        let mut triangle_count = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let content = line.split('#').next().unwrap_or("");
+            let mut tokens = content.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let arguments: Vec<&str> = tokens.collect();
+            match keyword {
+                "v" => {
+                    let [x, y, z] = numbers::<3>(line_number, keyword, &arguments, 3)?;
+                    positions.push(Vec3D::new(x, y, z));
+                }
+                "vt" => {
+                    let [u, v] = numbers::<2>(line_number, keyword, &arguments, 1)?;
+                    uvs.push((u, v));
+                }
+                "vn" => {
+                    let [x, y, z] = numbers::<3>(line_number, keyword, &arguments, 3)?;
+                    normals.push(Vec3D::new(x, y, z));
+                }
+                "f" => {
+                    if arguments.len() < 3 {
+                        return Err(ParseError::new(
+                            line_number,
+                            "a face needs at least 3 vertices".to_string(),
+                        )
+                        .into());
+                    }
+                    let mut corners = Vec::with_capacity(arguments.len());
+                    for argument in &arguments {
+                        corners.push(Corner::parse(
+                            line_number,
+                            argument,
+                            positions.len(),
+                            uvs.len(),
+                            normals.len(),
+                        )?);
+                    }
+                    // split the polygon in a fan of triangles around the first corner
+                    for i in 1..corners.len() - 1 {
+                        let face = [&corners[0], &corners[i], &corners[i + 1]];
+                        let mut triangle = Triangle::new(
+                            positions[face[0].position],
+                            positions[face[1].position],
+                            positions[face[2].position],
+                            material.clone(),
+                        );
+                        if let [Some(a), Some(b), Some(c)] = face.map(|corner| corner.uv) {
+                            triangle = triangle.with_uvs([uvs[a], uvs[b], uvs[c]]);
+                        }
+                        if let [Some(a), Some(b), Some(c)] = face.map(|corner| corner.normal) {
+                            triangle = triangle.with_normals([normals[a], normals[b], normals[c]]);
+                        }
+                        triangles.add(Arc::new(triangle));
+                        triangle_count += 1;
+                    }
+                }
+                "mtllib" => {
+                    if arguments.is_empty() {
+                        return Err(ParseError::new(
+                            line_number,
+                            "missing material library name".to_string(),
+                        )
+                        .into());
+                    }
+                    for library in &arguments {
+                        let text = read_library(library)?;
+                        let parsed = parse_mtl(&text).map_err(|error| ObjError::Parse {
+                            file: library.to_string(),
+                            error,
+                        })?;
+                        materials.extend(parsed);
+                    }
+                }
+                "usemtl" => {
+                    let name = match arguments.as_slice() {
+                        [name] => *name,
+                        _ => {
+                            return Err(ParseError::new(
+                                line_number,
+                                "expected 'usemtl <name>'".to_string(),
+                            )
+                            .into())
+                        }
+                    };
+                    material = match materials.get(name) {
+                        Some(material) => material.clone(),
+                        None => {
+                            return Err(ParseError::new(
+                                line_number,
+                                format!("unknown material '{}'", name),
+                            )
+                            .into())
+                        }
+                    };
+                }
+                // groups, objects, smoothing groups, lines and points don't change the surface
+                "o" | "g" | "s" | "l" | "p" | "vp" => {}
+                _ => {
+                    return Err(ParseError::new(
+                        line_number,
+                        format!("unknown statement '{}'", keyword),
+                    )
+                    .into())
+                }
+            }
+        }
+
+        Ok(Self {
+            triangles: BvhNode::new(triangles),
+            triangle_count,
+        })
+    }
+
+    /// Number of triangles in the mesh
+    pub fn len(&self) -> usize {
+        self.triangle_count
+    }
+
+    /// Determine whether the mesh has no triangles
+    pub fn is_empty(&self) -> bool {
+        self.triangle_count == 0
+    }
+}
+
+impl Hitable for Mesh {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        self.triangles.hit(ray, min, max, record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.triangles.bounding_box()
+    }
+}
+
+/// Indices (0 based) of the position, texture coordinates and normal of a face corner
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+impl Corner {
+    ///
+    /// Parse v, v/vt, v//vn or v/vt/vn. Indices start at 1, negative ones count
+    /// backwards from the last element declared.
+    ///
+    fn parse(
+        line: usize,
+        text: &str,
+        positions: usize,
+        uvs: usize,
+        normals: usize,
+    ) -> Result<Self, ParseError> {
+        let mut parts = text.split('/');
+        let index = |part: Option<&str>, count: usize, what: &str| match part {
+            None | Some("") => Ok(None),
+            Some(part) => {
+                let resolved = match part.parse::<i64>() {
+                    Ok(index) if index > 0 => Some(index as usize - 1),
+                    Ok(index) if index < 0 && index.unsigned_abs() as usize <= count => {
+                        Some(count - index.unsigned_abs() as usize)
+                    }
+                    _ => None,
+                };
+                match resolved {
+                    Some(resolved) if resolved < count => Ok(Some(resolved)),
+                    _ => Err(ParseError::new(
+                        line,
+                        format!("invalid {} index '{}' in '{}'", what, part, text),
+                    )),
+                }
+            }
+        };
+        let position = index(parts.next(), positions, "vertex")?
+            .ok_or_else(|| ParseError::new(line, format!("missing vertex index in '{}'", text)))?;
+        let uv = index(parts.next(), uvs, "texture coordinate")?;
+        let normal = index(parts.next(), normals, "normal")?;
+        if parts.next().is_some() {
+            return Err(ParseError::new(
+                line,
+                format!("invalid face vertex '{}'", text),
+            ));
+        }
+        Ok(Self {
+            position,
+            uv,
+            normal,
+        })
+    }
+}
+
+/// MTL parameters relevant to the materials supported
+struct MtlMaterial {
+    diffuse: Rgb,
+    specular: Rgb,
+    emissive: Rgb,
+    shininess: f32,
+    ior: f32,
+    opacity: f32,
+    /// PBR extension (Pr and Pm)
+    roughness: Option<f32>,
+    metallic: Option<f32>,
+}
+
+impl MtlMaterial {
+    fn new() -> Self {
+        Self {
+            diffuse: Rgb::new(0.8, 0.8, 0.8),
+            specular: Rgb::BLACK,
+            emissive: Rgb::BLACK,
+            shininess: 0.0,
+            ior: 1.5,
+            opacity: 1.0,
+            roughness: None,
+            metallic: None,
+        }
+    }
+
+    fn material(&self) -> Arc<dyn Material> {
+        if !self.emissive.is_black() {
+            Arc::new(DiffuseLight::new(self.emissive))
+        } else if self.opacity < 1.0 {
+            Arc::new(Dialectric::new(self.ior))
+        } else if self.roughness.is_some() || self.metallic.is_some() {
+            Arc::new(Principled::new(
+                self.diffuse,
+                self.metallic.unwrap_or(0.0),
+                self.roughness.unwrap_or(0.5),
+            ))
+        } else if self.specular.max_component() > self.diffuse.max_component() {
+            // Phong exponent to roughness: high exponents give sharp reflections
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new(self.specular, Some(fuzz)))
+        } else {
+            Arc::new(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+///
+/// Parse the materials of an MTL library, by name
+///
+pub fn parse_mtl(text: &str) -> Result<HashMap<String, Arc<dyn Material>>, ParseError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let content = line.split('#').next().unwrap_or("");
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            let name = match arguments.as_slice() {
+                [name] => name.to_string(),
+                _ => {
+                    return Err(ParseError::new(
+                        line_number,
+                        "expected 'newmtl <name>'".to_string(),
+                    ))
+                }
+            };
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.material());
+            }
+            current = Some((name, MtlMaterial::new()));
+            continue;
+        }
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None => {
+                return Err(ParseError::new(
+                    line_number,
+                    format!("'{}' before any newmtl", keyword),
+                ))
+            }
+        };
+        let color = |arguments: &[&str]| -> Result<Rgb, ParseError> {
+            let [r, g, b] = numbers::<3>(line_number, keyword, arguments, 1)?;
+            // a single value means gray
+            Ok(if arguments.len() == 1 {
+                Rgb::new(r, r, r)
+            } else {
+                Rgb::new(r, g, b)
+            })
+        };
+        let number = |arguments: &[&str]| -> Result<f32, ParseError> {
+            let [value] = numbers::<1>(line_number, keyword, arguments, 1)?;
+            Ok(value)
+        };
+        match keyword {
+            "Kd" => material.diffuse = color(&arguments)?,
+            "Ks" => material.specular = color(&arguments)?,
+            "Ke" => material.emissive = color(&arguments)?,
+            "Ns" => material.shininess = number(&arguments)?,
+            "Ni" => material.ior = number(&arguments)?,
+            "d" => material.opacity = number(&arguments)?,
+            "Tr" => material.opacity = 1.0 - number(&arguments)?,
+            "Pr" => material.roughness = Some(number(&arguments)?),
+            "Pm" => material.metallic = Some(number(&arguments)?),
+            // ambient color, illumination model, texture maps etc. aren't supported
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current.take() {
+        materials.insert(name, material.material());
+    }
+    Ok(materials)
+}
+
+///
+/// Parse between required and N numbers (the missing ones are 0)
+///
+fn numbers<const N: usize>(
+    line: usize,
+    keyword: &str,
+    arguments: &[&str],
+    required: usize,
+) -> Result<[f32; N], ParseError> {
+    // obj allows an extra optional component (w) after positions and texture coordinates,
+    // checked but ignored
+    let extra = matches!(keyword, "v" | "vt") as usize;
+    if arguments.len() < required || arguments.len() > N + extra {
+        return Err(ParseError::new(
+            line,
+            format!(
+                "'{}' expects {} numbers but got {}",
+                keyword,
+                N,
+                arguments.len()
+            ),
+        ));
+    }
+    let mut values = [0.0; N];
+    for (i, argument) in arguments.iter().enumerate() {
+        let value = f32::from_str(argument).map_err(|_| {
+            ParseError::new(
+                line,
+                format!("invalid number '{}' for '{}'", argument, keyword),
+            )
+        })?;
+        if i < N {
+            values[i] = value;
+        }
+    }
+    Ok(values)
+}
+
+impl ObjError {
+    /// Attach the file name to parse errors which don't have one yet
+    fn in_file(self, filename: &str) -> Self {
+        match self {
+            ObjError::Parse { file, error } if file.is_empty() => ObjError::Parse {
+                file: filename.to_string(),
+                error,
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(error) => write!(f, "{}", error),
+            ObjError::Parse { file, error } if file.is_empty() => write!(f, "{}", error),
+            ObjError::Parse { file, error } => write!(f, "{}: {}", file, error),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(error: io::Error) -> Self {
+        ObjError::Io(error)
+    }
+}
+
+impl From<ParseError> for ObjError {
+    fn from(error: ParseError) -> Self {
+        ObjError::Parse {
+            file: String::new(),
+            error,
+        }
+    }
+}
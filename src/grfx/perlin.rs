@@ -0,0 +1,130 @@
+use crate::grfx::vector::Vec3D;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+///
+/// Perlin (gradient) noise generator.
+///
+/// Random gradients are placed at the integer lattice points and blended smoothly in between,
+/// giving a continuous pseudo random value for every point of space.
+/// The same seed always gives the same noise, so renders are reproducible.
+///
+pub struct Perlin {
+    gradients: Vec<Vec3D>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let gradient = Vec3D::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                );
+                // uniform directions: keep the points inside the unit sphere
+                let length = gradient.squared_length();
+                if length > 1e-4 && length <= 1.0 {
+                    break gradient.unit_vector();
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut values: Vec<usize> = (0..POINT_COUNT).collect();
+            values.shuffle(&mut rng);
+            values
+        };
+        let permutations = [permutation(), permutation(), permutation()];
+        Self {
+            gradients,
+            permutations,
+        }
+    }
+
+    ///
+    /// Noise value at the given point, in [-1, 1] and 0 at the lattice points.
+    /// Features are about 1 unit in size, scale the point to change it.
+    ///
+    pub fn noise(&self, point: &Vec3D) -> f32 {
+        let floor = [point.x().floor(), point.y().floor(), point.z().floor()];
+        let offset = [
+            point.x() - floor[0],
+            point.y() - floor[1],
+            point.z() - floor[2],
+        ];
+        // smooth the blending so the noise has no visible grid (Hermite cubic)
+        let weight = offset.map(|t| t * t * (3.0 - 2.0 * t));
+        let cell = floor.map(|value| value as i64);
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let di = corner & 1;
+            let dj = (corner >> 1) & 1;
+            let dk = (corner >> 2) & 1;
+            let index = |axis: usize, delta: usize| {
+                self.permutations[axis][((cell[axis] + delta as i64) & 255) as usize]
+            };
+            let gradient = &self.gradients[index(0, di) ^ index(1, dj) ^ index(2, dk)];
+            let to_point = Vec3D::new(
+                offset[0] - di as f32,
+                offset[1] - dj as f32,
+                offset[2] - dk as f32,
+            );
+            let blend = |delta: usize, weight: f32| {
+                if delta == 1 {
+                    weight
+                } else {
+                    1.0 - weight
+                }
+            };
+            sum += blend(di, weight[0])
+                * blend(dj, weight[1])
+                * blend(dk, weight[2])
+                * Vec3D::dot(gradient, &to_point);
+        }
+        sum.clamp(-1.0, 1.0)
+    }
+
+    ///
+    /// Sum of the absolute noise over octaves (each one twice the frequency and half the
+    /// weight of the previous), gives the creases of marble veins and flames. In [0, 2].
+    ///
+    pub fn turbulence(&self, point: &Vec3D, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut point = *point;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            sum += weight * self.noise(&point).abs();
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        sum
+    }
+
+    ///
+    /// Fractal (fractional Brownian motion) noise: sum of the noise over octaves like turbulence
+    /// but keeping the sign, for rolling terrain and clouds. Normalized to [-1, 1].
+    ///
+    pub fn fractal(&self, point: &Vec3D, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut point = *point;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            sum += weight * self.noise(&point);
+            total += weight;
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+}
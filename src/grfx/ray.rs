@@ -1,15 +1,27 @@
 use crate::grfx::vector::Vec3D;
 
+/// Ray starting at A going in the direction B, at the given time (for motion blur)
+/// and carrying light of a single wavelength in nm when rendering spectrally
+/// (or once a dispersive material picked one of the RGB channels)
 #[derive(Debug,Default)]
 #[allow(non_snake_case)]
 pub struct Ray {
     A : Vec3D,
     B : Vec3D,
+    time : f32,
+    wavelength : Option<f32>,
 }
 
 impl Ray {
     pub fn new(a : Vec3D, b: Vec3D) -> Self {
-        Self{A: a, B: b}
+        Self{A: a, B: b, time: 0.0, wavelength: None}
+    }
+    pub fn with_time(a : Vec3D, b: Vec3D, time : f32) -> Self {
+        Self{A: a, B: b, time, wavelength: None}
+    }
+    pub fn with_wavelength(mut self, wavelength : Option<f32>) -> Self {
+        self.wavelength = wavelength;
+        self
     }
     pub fn point_at(&self, t : f32) -> Vec3D {
         self.A + self.B * t
@@ -20,4 +32,10 @@ impl Ray {
     pub fn direction(&self) -> Vec3D {
         self.B
     }
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+    pub fn wavelength(&self) -> Option<f32> {
+        self.wavelength
+    }
 }
\ No newline at end of file
@@ -0,0 +1,424 @@
+use crate::grfx::camera::Camera;
+use crate::grfx::color::Rgb;
+use crate::grfx::image::{HdrImage, Image};
+use crate::grfx::ray::Ray;
+use crate::grfx::shape::{HitRecord, Hitable};
+use crate::grfx::spectrum;
+use crate::grfx::vector::Vec3D;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+///
+/// What rays that don't hit anything see
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// Same color in every direction, Solid(Rgb::BLACK) for scenes only lit by lights
+    Solid(Rgb),
+    /// Vertical blend from bottom (looking down) to top (looking up), i.e. a sky
+    Gradient { bottom: Rgb, top: Rgb },
+}
+
+impl Background {
+    /// Color seen along the direction of the ray given
+    pub fn color(&self, ray: &Ray) -> Rgb {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let unit_direction = ray.direction().unit_vector();
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+        }
+    }
+}
+
+///
+/// Parameters used by the Renderer
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+    /// Rays averaged per pixel (anti-aliasing)
+    pub samples_per_pixel: u32,
+    /// Maximum number of bounces followed for each ray
+    pub max_depth: u32,
+    /// What rays escaping the scene see
+    pub background: Background,
+    /// Gamma used to encode the final colors (2.0 means square root)
+    pub gamma: f32,
+    /// Number of worker threads, 0 uses all the available cores
+    pub threads: usize,
+    /// Width and height (in pixels) of the tiles dispatched to the threads
+    pub tile_size: u32,
+    /// Seed for all the random sampling done while rendering
+    pub seed: u64,
+    /// Trace a single wavelength per sample instead of RGB colors, see Renderer
+    pub spectral: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 600,
+            height: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            background: Background::Gradient {
+                bottom: Rgb::WHITE,
+                top: Rgb::new(0.5, 0.7, 1.0),
+            },
+            gamma: 2.0,
+            threads: 0,
+            tile_size: 16,
+            seed: 0,
+            spectral: false,
+        }
+    }
+}
+
+///
+/// Path tracer turning a world of hitable items seen through a camera into an image.
+///
+/// let image = Renderer::new(RenderSettings::default()).render(&world, &camera);
+///
+/// Lights given with with_lights are also sampled directly at every diffuse bounce (next event
+/// estimation), combined with the bounces hitting them by chance using multiple importance
+/// sampling. Small lights then need a lot less samples to render without noise.
+///
+/// With the spectral setting every sample follows light of a single wavelength: the colors of
+/// materials, lights and background are upsampled to spectra (see grfx::spectrum) and the
+/// radiance found is converted back to RGB. Dispersive materials then split white light into
+/// a continuous rainbow, at the cost of some color noise.
+///
+#[derive(Clone, Default)]
+pub struct Renderer {
+    settings: RenderSettings,
+    lights: Vec<Arc<dyn Hitable>>,
+}
+
+impl Renderer {
+    /// Create a renderer using the settings given
+    pub fn new(settings: RenderSettings) -> Self {
+        Self {
+            settings,
+            lights: Vec::new(),
+        }
+    }
+
+    ///
+    /// Sample the given shapes as lights, they have to be part of the world too.
+    /// Only shapes implementing Hitable::sample_direction can be sampled.
+    ///
+    pub fn with_lights(mut self, lights: Vec<Arc<dyn Hitable>>) -> Self {
+        self.lights = lights;
+        self
+    }
+
+    /// Retrieve the render settings
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// Retrieve the lights sampled directly, see with_lights
+    pub fn lights(&self) -> &[Arc<dyn Hitable>] {
+        &self.lights
+    }
+
+    ///
+    /// Render the world as seen by the camera, quantized to 8 bits using the settings gamma.
+    ///
+    pub fn render(&self, world: &dyn Hitable, camera: &Camera) -> Image {
+        self.render_hdr(world, camera).to_image(self.settings.gamma)
+    }
+
+    ///
+    /// Render the world as seen by the camera keeping the linear (unclamped) colors.
+    /// Every pixel averages samples_per_pixel rays randomly spread over its area.
+    ///
+    pub fn render_hdr(&self, world: &dyn Hitable, camera: &Camera) -> HdrImage {
+        let settings = &self.settings;
+        let width = settings.width;
+        let height = settings.height;
+        let samples = settings.samples_per_pixel.max(1);
+        let tiles = TileRenderer {
+            tile_size: settings.tile_size,
+            threads: settings.threads,
+            seed: settings.seed,
+        };
+        let pixels = tiles.render(width, height, |x, y, rng| {
+            // (i, j) are the pixel coordinates with j growing upwards
+            let i = x;
+            let j = height - 1 - y;
+            let mut sum = Rgb::BLACK;
+            for sample in 0..samples {
+                let u = (i as f32 + rng.gen::<f32>()) / width as f32;
+                let v = (j as f32 + rng.gen::<f32>()) / height as f32;
+                let ray = camera.ray(u, v, rng);
+                if settings.spectral {
+                    // spread the wavelengths evenly over the samples of the pixel
+                    let offset = (sample as f32 + rng.gen::<f32>()) / samples as f32;
+                    let wavelength = spectrum::sample_wavelength(offset);
+                    let ray = ray.with_wavelength(Some(wavelength));
+                    let radiance = self.color(&ray, world, 0, None, rng);
+                    sum += spectrum::to_rgb(wavelength, radiance.g());
+                } else {
+                    sum += self.color(&ray, world, 0, None, rng);
+                }
+            }
+            sum / samples as f32
+        });
+        HdrImage::from_pixels(width, height, pixels)
+    }
+
+    ///
+    /// Color seen along a ray.
+    /// Follows the ray through the world scattering it according to the materials hit,
+    /// adding up the light given off by the surfaces it bounces on.
+    ///
+    /// bsdf_pdf is the density with which the previous (diffuse) bounce picked the ray,
+    /// None for camera rays and specular bounces whose lights weren't sampled.
+    ///
+    /// Spectral renders give the radiance at the wavelength of the ray in all three channels.
+    ///
+    fn color(
+        &self,
+        ray: &Ray,
+        world: &dyn Hitable,
+        depth: u32,
+        bsdf_pdf: Option<f32>,
+        rng: &mut dyn RngCore,
+    ) -> Rgb {
+        let mut rec = HitRecord::new();
+        if !world.hit(ray, 0.001, f32::MAX, &mut rec) {
+            return self.at_wavelength(ray, self.settings.background.color(ray));
+        }
+        let mut color = self.at_wavelength(ray, rec.material.emitted(rec.u, rec.v, &rec.point));
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            // the light may also have been reached by light sampling at the previous bounce
+            if !color.is_black() {
+                let light_pdf = self.light_pdf(&ray.origin(), &ray.direction());
+                color = color * power_heuristic(bsdf_pdf, light_pdf);
+            }
+        }
+        let mut scattered = Ray::new(Vec3D::new(0.0, 0.0, 0.0), Vec3D::new(0.0, 0.0, 0.0));
+        let mut attenuation = Rgb::BLACK;
+        if depth >= self.settings.max_depth
+            || !rec
+                .material
+                .scatter(ray, &rec, &mut attenuation, &mut scattered, rng)
+        {
+            return color;
+        }
+        let attenuation = self.at_wavelength(ray, attenuation);
+        // materials picking a wavelength (dispersion) give it to the scattered ray
+        let wavelength = scattered.wavelength().or(ray.wavelength());
+        let scattered = scattered.with_wavelength(wavelength);
+        let pdf = rec.material.scattering_pdf(ray, &rec, &scattered);
+        let next_pdf = if pdf > 0.0 && !self.lights.is_empty() {
+            color += self.sample_light(ray, &rec, world, rng);
+            Some(pdf)
+        } else {
+            None
+        };
+        color + attenuation * self.color(&scattered, world, depth + 1, next_pdf, rng)
+    }
+
+    ///
+    /// Light reaching the point hit straight from a light picked at random,
+    /// weighted against finding the same light by scattering
+    ///
+    fn sample_light(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hitable,
+        rng: &mut dyn RngCore,
+    ) -> Rgb {
+        let light = &self.lights[rng.gen_range(0..self.lights.len())];
+        let direction = match light.sample_direction(&rec.point, rng) {
+            Some(direction) => direction,
+            None => return Rgb::BLACK,
+        };
+        let light_pdf = self.light_pdf(&rec.point, &direction);
+        if light_pdf <= 0.0 {
+            return Rgb::BLACK;
+        }
+        let shadow =
+            Ray::with_time(rec.point, direction, ray.time()).with_wavelength(ray.wavelength());
+        let bsdf = self.at_wavelength(ray, rec.material.eval(ray, rec, &shadow));
+        if bsdf.is_black() {
+            return Rgb::BLACK;
+        }
+        // whatever is hit first (the light or what hides it) gives the light received
+        let mut light_rec = HitRecord::new();
+        if !world.hit(&shadow, 0.001, f32::MAX, &mut light_rec) {
+            return Rgb::BLACK;
+        }
+        let emitted = self.at_wavelength(
+            ray,
+            light_rec
+                .material
+                .emitted(light_rec.u, light_rec.v, &light_rec.point),
+        );
+        if emitted.is_black() {
+            return Rgb::BLACK;
+        }
+        let bsdf_pdf = rec.material.scattering_pdf(ray, rec, &shadow);
+        bsdf * emitted * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+
+    ///
+    /// Value at the wavelength of the ray of the spectrum upsampled from the color (repeated
+    /// in the three channels) for spectral renders, the color itself otherwise
+    ///
+    fn at_wavelength(&self, ray: &Ray, color: Rgb) -> Rgb {
+        match ray.wavelength() {
+            Some(wavelength) if self.settings.spectral => {
+                let value = spectrum::from_rgb(&color, wavelength);
+                Rgb::new(value, value, value)
+            }
+            _ => color,
+        }
+    }
+
+    /// Density of light sampling picking the direction, lights are picked with the same probability
+    fn light_pdf(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        let sum: f32 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(origin, direction))
+            .sum();
+        sum / self.lights.len() as f32
+    }
+}
+
+impl fmt::Debug for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Renderer")
+            .field("settings", &self.settings)
+            .field("lights", &self.lights.len())
+            .finish()
+    }
+}
+
+///
+/// Weight of a sample taken with the first strategy when the second one could have given it too
+/// (Veach's power heuristic with an exponent of 2)
+///
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+    // same as pdf² / (pdf² + other_pdf²) without overflowing
+    let ratio = other_pdf / pdf;
+    1.0 / (1.0 + ratio * ratio)
+}
+
+///
+/// Multithreaded tile based renderer.
+///
+/// The image is split in square tiles which are handed out to a pool of worker threads.
+/// Every pixel gets its own random number generator seeded from the renderer seed and the
+/// pixel position, so the output for a given seed is identical regardless of the number of
+/// threads or the tile size used.
+///
+#[derive(Debug, Clone)]
+pub struct TileRenderer {
+    /// Width and height (in pixels) of the tiles dispatched to the threads
+    pub tile_size: u32,
+    /// Number of worker threads, 0 uses all the available cores
+    pub threads: usize,
+    /// Seed for all the random sampling done while rendering
+    pub seed: u64,
+}
+
+impl Default for TileRenderer {
+    fn default() -> Self {
+        Self {
+            tile_size: 16,
+            threads: 0,
+            seed: 0,
+        }
+    }
+}
+
+impl TileRenderer {
+    ///
+    /// Render a width x height image by calling shade for each pixel.
+    ///
+    /// shade receives the pixel coordinates (x, y), with (0, 0) being the top left corner,
+    /// and a random number generator dedicated to that pixel.
+    /// Returns the pixels row by row starting from the top.
+    ///
+    pub fn render<T, F>(&self, width: u32, height: u32, shade: F) -> Vec<T>
+    where
+        T: Clone + Default + Send,
+        F: Fn(u32, u32, &mut dyn RngCore) -> T + Sync,
+    {
+        let tile_size = self.tile_size.max(1);
+        let tiles_x = width.div_ceil(tile_size);
+        let tiles_y = height.div_ceil(tile_size);
+        let tile_count = (tiles_x * tiles_y) as usize;
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+        .min(tile_count.max(1));
+
+        let next_tile = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        let mut pixels = vec![T::default(); (width * height) as usize];
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let next_tile = &next_tile;
+                let shade = &shade;
+                scope.spawn(move || loop {
+                    let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if tile >= tile_count {
+                        break;
+                    }
+                    let x0 = (tile as u32 % tiles_x) * tile_size;
+                    let y0 = (tile as u32 / tiles_x) * tile_size;
+                    let x1 = (x0 + tile_size).min(width);
+                    let y1 = (y0 + tile_size).min(height);
+                    let mut colors = Vec::with_capacity(((x1 - x0) * (y1 - y0)) as usize);
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            let index = (y * width + x) as u64;
+                            let mut rng = StdRng::seed_from_u64(pixel_seed(self.seed, index));
+                            colors.push(((y * width + x) as usize, shade(x, y, &mut rng)));
+                        }
+                    }
+                    // the receiver outlives the workers, sending can't fail
+                    sender.send(colors).unwrap();
+                });
+            }
+            drop(sender);
+            for colors in receiver {
+                for (index, color) in colors {
+                    pixels[index] = color;
+                }
+            }
+        });
+        pixels
+    }
+}
+
+/// Mix the renderer seed with the pixel index (splitmix64) so neighbouring pixels get unrelated streams
+fn pixel_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,106 @@
+use crate::grfx::vector::Vec3D;
+use rand::{Rng, RngCore};
+use std::f32::consts::PI;
+
+//
+// Random directions and points following known distributions, along with their densities
+// (probability per unit of solid angle or area) needed to weight the samples.
+//
+// Directions are given in local coordinates where z is the axis (i.e. the surface normal),
+// use Vec3D::local_to_world to orient them.
+//
+
+/// Direction uniformly distributed over the unit sphere
+pub fn uniform_sphere(rng: &mut dyn RngCore) -> Vec3D {
+    let z = 1.0 - 2.0 * rng.gen::<f32>();
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    Vec3D::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (4.0 * PI)
+}
+
+/// Direction uniformly distributed over the hemisphere around z
+pub fn uniform_hemisphere(rng: &mut dyn RngCore) -> Vec3D {
+    let z = rng.gen::<f32>();
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    Vec3D::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+pub fn uniform_hemisphere_pdf() -> f32 {
+    1.0 / (2.0 * PI)
+}
+
+///
+/// Direction over the hemisphere around z with a density proportional to the cosine
+/// of its angle with z, the distribution of light scattered by matte (Lambertian) surfaces.
+/// Points uniform over the disk lifted up to the hemisphere (Malley's method).
+///
+pub fn cosine_hemisphere(rng: &mut dyn RngCore) -> Vec3D {
+    let (x, y) = uniform_disk(rng);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    Vec3D::new(x, y, z)
+}
+
+/// Density of cosine_hemisphere for a direction with the given cosine (z)
+pub fn cosine_hemisphere_pdf(cosine: f32) -> f32 {
+    cosine.max(0.0) / PI
+}
+
+/// Point uniformly distributed over the unit disk
+pub fn uniform_disk(rng: &mut dyn RngCore) -> (f32, f32) {
+    // the square root spreads the points evenly instead of bunching them in the center
+    let radius = rng.gen::<f32>().sqrt();
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    (radius * phi.cos(), radius * phi.sin())
+}
+
+///
+/// Direction uniformly distributed inside the cone around z with the given half angle cosine,
+/// i.e. the directions towards a sphere
+///
+pub fn uniform_cone(cos_theta_max: f32, rng: &mut dyn RngCore) -> Vec3D {
+    let z = 1.0 + rng.gen::<f32>() * (cos_theta_max - 1.0);
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    Vec3D::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+///
+/// GGX (Trowbridge-Reitz) microfacet distribution: density of microfacet normals with the
+/// given cosine to the macro surface normal (z). alpha is the roughness, from 0 (smooth)
+/// to 1 (very rough), usually the square of the artist facing roughness.
+///
+pub fn ggx_distribution(alpha: f32, cosine: f32) -> f32 {
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let denominator = cosine * cosine * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+///
+/// Microfacet normal around z distributed following the GGX lobe weighted by its cosine,
+/// see ggx_distribution. Reflecting around it gives glossy reflections.
+///
+pub fn ggx_normal(alpha: f32, rng: &mut dyn RngCore) -> Vec3D {
+    let xi = rng.gen::<f32>();
+    let tan2 = alpha * alpha * xi / (1.0 - xi).max(f32::EPSILON);
+    let z = 1.0 / (1.0 + tan2).sqrt();
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    Vec3D::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+/// Density (per solid angle) of ggx_normal giving a normal with the given cosine (z)
+pub fn ggx_normal_pdf(alpha: f32, cosine: f32) -> f32 {
+    ggx_distribution(alpha, cosine) * cosine.max(0.0)
+}
@@ -0,0 +1,1623 @@
+use crate::grfx::camera::Camera;
+use crate::grfx::color::Rgb;
+use crate::grfx::material::{
+    Dialectric, DiffuseLight, Dispersion, Isotropic, Lambertian, Material, Metal, Principled,
+};
+use crate::grfx::medium::ConstantMedium;
+use crate::grfx::render::{Background, RenderSettings};
+use crate::grfx::shape::{
+    Cone, Cuboid, Cylinder, Disk, HitList, Hitable, MovingSphere, Plane, Rect, Sphere, Torus,
+    Triangle,
+};
+use crate::grfx::texture::{
+    Checker, ImageTexture, NoisePattern, NoiseTexture, SolidColor, Texture,
+};
+use crate::grfx::vector::Vec3D;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+
+///
+/// Scene description: render settings, camera, named textures and materials and shapes.
+///
+/// Scenes are written in a line based text format, one directive per line.
+/// Values are numbers or comma separated vectors/colors, '#' starts a comment:
+///
+/// ```text
+/// settings width=600 height=400 samples=100 depth=50 gamma=2 seed=0   (spectral=true to render spectrally)
+/// background gradient bottom=1,1,1 top=0.5,0.7,1   (or: black, solid color=r,g,b)
+/// camera from=6,1,2 at=4,1,1 up=0,1,0 fov=60 aperture=0.01 shutter=0,1
+/// texture checks checker even=0.2,0.3,0.1 odd=0.9,0.9,0.9 scale=1   (or: solid color=r,g,b)
+/// texture earth image file=textures/earth.png   (path relative to the working directory)
+/// texture stone noise pattern=marble scale=4 seed=0 octaves=7 low=0,0,0 high=1,1,1
+///     (patterns: smooth, turbulence, fractal, marble, wood, only pattern is required)
+/// material ground lambertian albedo=0.5,0.5,0.5   (or texture=checks, for metals too)
+/// material mirror metal albedo=0.7,0.6,0.5 fuzz=0
+/// material glass dielectric ior=1.5
+///     (optional: exterior=1.33 for the medium around, tint=0.8,0.9,0.8 tint_distance=1 for light
+///     absorbed going through, thin=0.01 for sheets of that thickness, cauchy=a,b or
+///     sellmeier=b1,b2,b3,c1,c2,c3 for dispersion, in which case ior isn't given)
+/// material lamp light emit=4,4,4
+/// material smoke isotropic albedo=0.8,0.8,0.8   (or texture=, scattering inside media)
+/// material gold principled albedo=1,0.78,0.34 metallic=1 roughness=0.3 specular=0.5
+///     (or texture=, metallic, roughness and specular default to 0, 0.5 and 0.5)
+/// sphere center=0,-1000,0 radius=1000 material=ground
+/// moving_sphere from=0,1,0 to=0,1.5,0 radius=0.5 material=ground   (from at time 0, to at time 1)
+/// triangle a=0,0,0 b=1,0,0 c=0,1,0 material=ground
+/// plane point=0,0,0 normal=0,1,0 material=ground
+/// disk center=0,3,0 normal=0,-1,0 radius=1 material=lamp
+/// rect x=-1,1 z=-1,1 y=3 material=lamp   (two ranges and the position on the third axis)
+/// box min=-1,0,-1 max=1,2,1 material=ground
+/// cylinder base=0,0,0 radius=1 height=2 material=ground   (cylinders and cones stand along y)
+/// cone base=0,0,0 radius=1 height=2 material=ground
+/// torus center=0,1,0 major=1 minor=0.25 material=ground   (lying on the xz plane)
+/// medium density=0.5 sphere center=0,1,0 radius=1 material=smoke
+///     (fog filling a sphere, moving_sphere, box, cylinder or cone, scattering with the shape material)
+/// ```
+///
+/// Textures have to be declared before the materials using them and materials before the shapes.
+/// Scene::parse reads the format, Display writes it back.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub settings: RenderSettings,
+    pub camera: CameraDesc,
+    pub textures: Vec<(String, TextureDesc)>,
+    pub materials: Vec<(String, MaterialDesc)>,
+    pub shapes: Vec<ShapeDesc>,
+}
+
+/// Camera parameters, see Camera::new
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraDesc {
+    pub look_from: Vec3D,
+    pub look_at: Vec3D,
+    pub vup: Vec3D,
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    pub aperture: f32,
+    /// Distance to the focus plane, defaults to the distance between look_from and look_at
+    pub focus_dist: Option<f32>,
+    /// Times at which the shutter opens and closes, see Camera::with_shutter
+    pub shutter: (f32, f32),
+}
+
+/// Texture parameters
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureDesc {
+    Solid {
+        color: Rgb,
+    },
+    Checker {
+        even: Rgb,
+        odd: Rgb,
+        scale: f32,
+    },
+    Image {
+        file: String,
+    },
+    /// Perlin noise, see NoiseTexture
+    Noise {
+        pattern: NoisePattern,
+        scale: f32,
+        seed: u64,
+        octaves: u32,
+        low: Rgb,
+        high: Rgb,
+    },
+}
+
+/// Albedo of a material: a color or a texture by name
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlbedoDesc {
+    Color(Rgb),
+    Texture(String),
+}
+
+/// Material parameters
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: AlbedoDesc,
+    },
+    Metal {
+        albedo: AlbedoDesc,
+        fuzz: Option<f32>,
+    },
+    Dielectric {
+        /// Taken from the dispersion for dispersive materials
+        ior: f32,
+        exterior_ior: f32,
+        /// Color kept after the distance travelled inside
+        tint: Option<(Rgb, f32)>,
+        dispersion: Option<Dispersion>,
+        /// Thickness of thin walled materials
+        thin: Option<f32>,
+    },
+    Light {
+        emit: Rgb,
+    },
+    /// Phase function of media, see Isotropic
+    Isotropic {
+        albedo: AlbedoDesc,
+    },
+    /// glTF like metallic/roughness material, see Principled
+    Principled {
+        albedo: AlbedoDesc,
+        metallic: f32,
+        roughness: f32,
+        specular: f32,
+    },
+}
+
+/// Shape parameters, material refers to a material by name
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapeDesc {
+    Sphere {
+        center: Vec3D,
+        radius: f32,
+        material: String,
+    },
+    /// Sphere moving from one center at time 0 to the other at time 1
+    MovingSphere {
+        from: Vec3D,
+        to: Vec3D,
+        radius: f32,
+        material: String,
+    },
+    Triangle {
+        vertices: [Vec3D; 3],
+        material: String,
+    },
+    Plane {
+        point: Vec3D,
+        normal: Vec3D,
+        material: String,
+    },
+    Disk {
+        center: Vec3D,
+        normal: Vec3D,
+        radius: f32,
+        material: String,
+    },
+    /// Axis aligned rectangle, see Rect::new
+    Rect {
+        axis: usize,
+        min: (f32, f32),
+        max: (f32, f32),
+        k: f32,
+        material: String,
+    },
+    Box {
+        min: Vec3D,
+        max: Vec3D,
+        material: String,
+    },
+    Cylinder {
+        base: Vec3D,
+        radius: f32,
+        height: f32,
+        material: String,
+    },
+    Cone {
+        base: Vec3D,
+        radius: f32,
+        height: f32,
+        material: String,
+    },
+    Torus {
+        center: Vec3D,
+        major_radius: f32,
+        minor_radius: f32,
+        material: String,
+    },
+    /// Constant density medium filling the boundary shape, see ConstantMedium
+    Medium {
+        boundary: Box<ShapeDesc>,
+        density: f32,
+    },
+}
+
+/// Error found while parsing a scene, with the (1 based) line where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Error loading a scene from a file
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(ParseError),
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self {
+            look_from: Vec3D::new(0.0, 0.0, 0.0),
+            look_at: Vec3D::new(0.0, 0.0, -1.0),
+            vup: Vec3D::new(0.0, 1.0, 0.0),
+            fov: 90.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter: (0.0, 0.0),
+        }
+    }
+}
+
+impl CameraDesc {
+    /// Create the camera for an image with the given aspect ratio (width / height)
+    pub fn camera(&self, aspect: f32) -> Camera {
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (self.look_from - self.look_at).length());
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            self.fov,
+            aspect,
+            self.aperture,
+            focus_dist,
+        )
+        .with_shutter(self.shutter.0, self.shutter.1)
+    }
+}
+
+impl TextureDesc {
+    /// Create the texture described, image textures are read from their file
+    pub fn texture(&self) -> Result<Arc<dyn Texture>, std::io::Error> {
+        Ok(match self {
+            TextureDesc::Solid { color } => Arc::new(SolidColor::new(*color)),
+            TextureDesc::Checker { even, odd, scale } => {
+                Arc::new(Checker::colors(*even, *odd, *scale))
+            }
+            TextureDesc::Noise {
+                pattern,
+                scale,
+                seed,
+                octaves,
+                low,
+                high,
+            } => Arc::new(
+                NoiseTexture::new(*seed, *pattern, *scale)
+                    .with_octaves(*octaves)
+                    .with_colors(*low, *high),
+            ),
+            TextureDesc::Image { file } => match ImageTexture::load(file) {
+                Ok(texture) => Arc::new(texture),
+                Err(error) => {
+                    return Err(std::io::Error::new(
+                        error.kind(),
+                        format!("{}: {}", file, error),
+                    ))
+                }
+            },
+        })
+    }
+}
+
+impl MaterialDesc {
+    ///
+    /// Create the material described, textures are looked up by name.
+    /// Panics if the texture used isn't given (can't happen for parsed scenes).
+    ///
+    pub fn material(&self, textures: &HashMap<&str, Arc<dyn Texture>>) -> Arc<dyn Material> {
+        let albedo = |albedo: &AlbedoDesc| -> Arc<dyn Texture> {
+            match albedo {
+                AlbedoDesc::Color(color) => Arc::new(SolidColor::new(*color)),
+                AlbedoDesc::Texture(name) => match textures.get(name.as_str()) {
+                    Some(texture) => texture.clone(),
+                    None => panic!("Unknown texture '{}'", name),
+                },
+            }
+        };
+        match self {
+            MaterialDesc::Lambertian { albedo: desc } => {
+                Arc::new(Lambertian::textured(albedo(desc)))
+            }
+            MaterialDesc::Metal { albedo: desc, fuzz } => {
+                Arc::new(Metal::textured(albedo(desc), *fuzz))
+            }
+            MaterialDesc::Dielectric {
+                ior,
+                exterior_ior,
+                tint,
+                dispersion,
+                thin,
+            } => {
+                let mut material = match dispersion {
+                    Some(dispersion) => Dialectric::dispersive(*dispersion),
+                    None => Dialectric::new(*ior),
+                }
+                .with_exterior_ior(*exterior_ior);
+                if let Some((color, distance)) = tint {
+                    material = material.with_tint(*color, *distance);
+                }
+                if let Some(thickness) = thin {
+                    material = material.thin_walled(*thickness);
+                }
+                Arc::new(material)
+            }
+            MaterialDesc::Light { emit } => Arc::new(DiffuseLight::new(*emit)),
+            MaterialDesc::Isotropic { albedo: desc } => Arc::new(Isotropic::textured(albedo(desc))),
+            MaterialDesc::Principled {
+                albedo: desc,
+                metallic,
+                roughness,
+                specular,
+            } => Arc::new(
+                Principled::textured(albedo(desc), *metallic, *roughness).with_specular(*specular),
+            ),
+        }
+    }
+}
+
+impl ShapeDesc {
+    ///
+    /// Create the shape described, materials are looked up by name.
+    /// Panics if the material used isn't given (can't happen for parsed scenes).
+    ///
+    pub fn hitable(&self, materials: &HashMap<&str, Arc<dyn Material>>) -> Arc<dyn Hitable> {
+        let material = |name: &str| match materials.get(name) {
+            Some(material) => material.clone(),
+            None => panic!("Unknown material '{}'", name),
+        };
+        match self {
+            ShapeDesc::Sphere {
+                center,
+                radius,
+                material: name,
+            } => Arc::new(Sphere::new(*center, *radius, material(name))),
+            ShapeDesc::MovingSphere {
+                from,
+                to,
+                radius,
+                material: name,
+            } => Arc::new(MovingSphere::new(
+                *from,
+                0.0,
+                *to,
+                1.0,
+                *radius,
+                material(name),
+            )),
+            ShapeDesc::Triangle {
+                vertices: [a, b, c],
+                material: name,
+            } => Arc::new(Triangle::new(*a, *b, *c, material(name))),
+            ShapeDesc::Plane {
+                point,
+                normal,
+                material: name,
+            } => Arc::new(Plane::new(*point, *normal, material(name))),
+            ShapeDesc::Disk {
+                center,
+                normal,
+                radius,
+                material: name,
+            } => Arc::new(Disk::new(*center, *normal, *radius, material(name))),
+            ShapeDesc::Rect {
+                axis,
+                min,
+                max,
+                k,
+                material: name,
+            } => Arc::new(Rect::new(*axis, *min, *max, *k, material(name))),
+            ShapeDesc::Box {
+                min,
+                max,
+                material: name,
+            } => Arc::new(Cuboid::new(*min, *max, material(name))),
+            ShapeDesc::Cylinder {
+                base,
+                radius,
+                height,
+                material: name,
+            } => Arc::new(Cylinder::new(*base, *radius, *height, material(name))),
+            ShapeDesc::Cone {
+                base,
+                radius,
+                height,
+                material: name,
+            } => Arc::new(Cone::new(*base, *radius, *height, material(name))),
+            ShapeDesc::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                material: name,
+            } => Arc::new(Torus::new(
+                *center,
+                *major_radius,
+                *minor_radius,
+                material(name),
+            )),
+            ShapeDesc::Medium { boundary, density } => Arc::new(ConstantMedium::new(
+                boundary.hitable(materials),
+                *density,
+                material(boundary.material()),
+            )),
+        }
+    }
+
+    /// Name of the material of the shape (the phase function for media)
+    pub fn material(&self) -> &str {
+        match self {
+            ShapeDesc::Sphere { material, .. }
+            | ShapeDesc::MovingSphere { material, .. }
+            | ShapeDesc::Triangle { material, .. }
+            | ShapeDesc::Plane { material, .. }
+            | ShapeDesc::Disk { material, .. }
+            | ShapeDesc::Rect { material, .. }
+            | ShapeDesc::Box { material, .. }
+            | ShapeDesc::Cylinder { material, .. }
+            | ShapeDesc::Cone { material, .. }
+            | ShapeDesc::Torus { material, .. } => material,
+            ShapeDesc::Medium { boundary, .. } => boundary.material(),
+        }
+    }
+}
+
+impl Scene {
+    /// Create an empty scene with default settings and camera
+    pub fn new() -> Self {
+        Self {
+            settings: RenderSettings::default(),
+            camera: CameraDesc::default(),
+            textures: Vec::new(),
+            materials: Vec::new(),
+            shapes: Vec::new(),
+        }
+    }
+
+    ///
+    /// Read a scene from a file
+    ///
+    pub fn load(filename: &str) -> Result<Self, SceneError> {
+        let text = fs::read_to_string(filename)?;
+        Ok(Self::parse(&text)?)
+    }
+
+    ///
+    /// Write the scene to a file
+    ///
+    pub fn save(&self, filename: &str) -> Result<(), std::io::Error> {
+        fs::write(filename, self.to_string())
+    }
+
+    ///
+    /// Parse a scene from its text description
+    ///
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut scene = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let content = line.split('#').next().unwrap_or("");
+            let tokens: Vec<&str> = content.split_whitespace().collect();
+            let (directive, rest) = match tokens.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            match *directive {
+                "settings" => scene.parse_settings(line_number, rest)?,
+                "background" => scene.parse_background(line_number, rest)?,
+                "camera" => scene.parse_camera(line_number, rest)?,
+                "texture" => scene.parse_texture(line_number, rest)?,
+                "material" => scene.parse_material(line_number, rest)?,
+                "medium" => scene.parse_medium(line_number, rest)?,
+                _ => scene.parse_shape(line_number, directive, rest)?,
+            }
+        }
+        Ok(scene)
+    }
+
+    ///
+    /// Create all the shapes of the scene, with their materials.
+    /// Fails if an image texture can't be read.
+    ///
+    /// Panics if a shape uses a material which isn't declared (can't happen for parsed scenes).
+    ///
+    pub fn world(&self) -> Result<HitList, SceneError> {
+        let mut textures: HashMap<&str, Arc<dyn Texture>> = HashMap::new();
+        for (name, desc) in &self.textures {
+            textures.insert(name.as_str(), desc.texture()?);
+        }
+        let materials: HashMap<&str, Arc<dyn Material>> = self
+            .materials
+            .iter()
+            .map(|(name, desc)| (name.as_str(), desc.material(&textures)))
+            .collect();
+        let mut world = HitList::new();
+        for shape in &self.shapes {
+            world.add(shape.hitable(&materials));
+        }
+        Ok(world)
+    }
+
+    ///
+    /// Create the shapes with a light material which can be sampled as lights
+    /// (spheres, triangles, disks and rects), see Renderer::with_lights
+    ///
+    pub fn lights(&self) -> Vec<Arc<dyn Hitable>> {
+        let textures = HashMap::new();
+        let materials: HashMap<&str, Arc<dyn Material>> = self
+            .materials
+            .iter()
+            .filter(|(_, desc)| matches!(desc, MaterialDesc::Light { .. }))
+            .map(|(name, desc)| (name.as_str(), desc.material(&textures)))
+            .collect();
+        self.shapes
+            .iter()
+            .filter(|shape| {
+                matches!(
+                    shape,
+                    ShapeDesc::Sphere { .. }
+                        | ShapeDesc::Triangle { .. }
+                        | ShapeDesc::Disk { .. }
+                        | ShapeDesc::Rect { .. }
+                ) && materials.contains_key(shape.material())
+            })
+            .map(|shape| shape.hitable(&materials))
+            .collect()
+    }
+
+    ///
+    /// Create the camera, with the aspect ratio of the image in the settings
+    ///
+    pub fn camera(&self) -> Camera {
+        self.camera
+            .camera(self.settings.width as f32 / self.settings.height as f32)
+    }
+
+    /// Find a texture declared in the scene by its name
+    pub fn texture(&self, name: &str) -> Option<&TextureDesc> {
+        self.textures
+            .iter()
+            .find(|(texture, _)| texture == name)
+            .map(|(_, desc)| desc)
+    }
+
+    /// Find a material declared in the scene by its name
+    pub fn material(&self, name: &str) -> Option<&MaterialDesc> {
+        self.materials
+            .iter()
+            .find(|(material, _)| material == name)
+            .map(|(_, desc)| desc)
+    }
+
+    fn parse_settings(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "settings", tokens)?;
+        let settings = &mut self.settings;
+        settings.width = params.value("width")?.unwrap_or(settings.width);
+        settings.height = params.value("height")?.unwrap_or(settings.height);
+        settings.samples_per_pixel = params
+            .value("samples")?
+            .unwrap_or(settings.samples_per_pixel);
+        settings.max_depth = params.value("depth")?.unwrap_or(settings.max_depth);
+        settings.gamma = params.value("gamma")?.unwrap_or(settings.gamma);
+        settings.threads = params.value("threads")?.unwrap_or(settings.threads);
+        settings.tile_size = params.value("tile")?.unwrap_or(settings.tile_size);
+        settings.seed = params.value("seed")?.unwrap_or(settings.seed);
+        settings.spectral = params.value("spectral")?.unwrap_or(settings.spectral);
+        if settings.width == 0 || settings.height == 0 {
+            return Err(ParseError::new(line, "image size can't be 0".to_string()));
+        }
+        params.finish()
+    }
+
+    fn parse_background(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let (kind, tokens) = match tokens.split_first() {
+            Some(split) => split,
+            None => return Err(ParseError::new(line, "missing background type".to_string())),
+        };
+        let mut params = Params::parse(line, "background", tokens)?;
+        self.settings.background = match *kind {
+            "black" => Background::Solid(Rgb::BLACK),
+            "solid" => Background::Solid(params.required_rgb("color")?),
+            "gradient" => Background::Gradient {
+                bottom: params.required_rgb("bottom")?,
+                top: params.required_rgb("top")?,
+            },
+            _ => {
+                return Err(ParseError::new(
+                    line,
+                    format!("unknown background type '{}'", kind),
+                ))
+            }
+        };
+        params.finish()
+    }
+
+    fn parse_camera(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "camera", tokens)?;
+        let camera = &mut self.camera;
+        camera.look_from = params.vector("from")?.unwrap_or(camera.look_from);
+        camera.look_at = params.vector("at")?.unwrap_or(camera.look_at);
+        camera.vup = params.vector("up")?.unwrap_or(camera.vup);
+        camera.fov = params.value("fov")?.unwrap_or(camera.fov);
+        camera.aperture = params.value("aperture")?.unwrap_or(camera.aperture);
+        camera.focus_dist = params.value("focus")?.or(camera.focus_dist);
+        camera.shutter = params.range("shutter")?.unwrap_or(camera.shutter);
+        if camera.look_from == camera.look_at {
+            return Err(ParseError::new(
+                line,
+                "camera can't look at its own position".to_string(),
+            ));
+        }
+        params.finish()
+    }
+
+    fn parse_texture(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let (name, kind, tokens) = match tokens {
+            [name, kind, rest @ ..] if !name.contains('=') => (*name, *kind, rest),
+            _ => {
+                return Err(ParseError::new(
+                    line,
+                    "expected 'texture <name> <type>'".to_string(),
+                ))
+            }
+        };
+        if self.texture(name).is_some() {
+            return Err(ParseError::new(
+                line,
+                format!("texture '{}' is already declared", name),
+            ));
+        }
+        let mut params = Params::parse(line, kind, tokens)?;
+        let texture = match kind {
+            "solid" => TextureDesc::Solid {
+                color: params.required_rgb("color")?,
+            },
+            "checker" => TextureDesc::Checker {
+                even: params.required_rgb("even")?,
+                odd: params.required_rgb("odd")?,
+                scale: params.value("scale")?.unwrap_or(1.0),
+            },
+            "image" => TextureDesc::Image {
+                file: params.required_text("file")?.to_string(),
+            },
+            "noise" => {
+                let name = params.required_text("pattern")?;
+                let pattern = match NoisePattern::from_name(name) {
+                    Some(pattern) => pattern,
+                    None => {
+                        return Err(ParseError::new(
+                            line,
+                            format!("unknown noise pattern '{}'", name),
+                        ))
+                    }
+                };
+                TextureDesc::Noise {
+                    pattern,
+                    scale: params.value("scale")?.unwrap_or(1.0),
+                    seed: params.value("seed")?.unwrap_or(0),
+                    octaves: params.value("octaves")?.unwrap_or(7),
+                    low: params.rgb("low")?.unwrap_or(Rgb::BLACK),
+                    high: params.rgb("high")?.unwrap_or(Rgb::WHITE),
+                }
+            }
+            _ => {
+                return Err(ParseError::new(
+                    line,
+                    format!("unknown texture type '{}'", kind),
+                ))
+            }
+        };
+        params.finish()?;
+        self.textures.push((name.to_string(), texture));
+        Ok(())
+    }
+
+    fn parse_material(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let (name, kind, tokens) = match tokens {
+            [name, kind, rest @ ..] if !name.contains('=') => (*name, *kind, rest),
+            _ => {
+                return Err(ParseError::new(
+                    line,
+                    "expected 'material <name> <type>'".to_string(),
+                ))
+            }
+        };
+        if self.material(name).is_some() {
+            return Err(ParseError::new(
+                line,
+                format!("material '{}' is already declared", name),
+            ));
+        }
+        let mut params = Params::parse(line, kind, tokens)?;
+        let material = match kind {
+            "lambertian" => MaterialDesc::Lambertian {
+                albedo: self.albedo(&mut params)?,
+            },
+            "metal" => MaterialDesc::Metal {
+                albedo: self.albedo(&mut params)?,
+                fuzz: params.value("fuzz")?,
+            },
+            "dielectric" => self.dielectric(&mut params)?,
+            "light" => MaterialDesc::Light {
+                emit: params.required_rgb("emit")?,
+            },
+            "isotropic" => MaterialDesc::Isotropic {
+                albedo: self.albedo(&mut params)?,
+            },
+            "principled" => MaterialDesc::Principled {
+                albedo: self.albedo(&mut params)?,
+                metallic: params.value("metallic")?.unwrap_or(0.0),
+                roughness: params.value("roughness")?.unwrap_or(0.5),
+                specular: params.value("specular")?.unwrap_or(0.5),
+            },
+            _ => {
+                return Err(ParseError::new(
+                    line,
+                    format!("unknown material type '{}'", kind),
+                ))
+            }
+        };
+        params.finish()?;
+        self.materials.push((name.to_string(), material));
+        Ok(())
+    }
+
+    /// Parse the shape directives
+    fn parse_shape(
+        &mut self,
+        line: usize,
+        directive: &str,
+        tokens: &[&str],
+    ) -> Result<(), ParseError> {
+        match directive {
+            "sphere" => self.parse_sphere(line, tokens),
+            "moving_sphere" => self.parse_moving_sphere(line, tokens),
+            "triangle" => self.parse_triangle(line, tokens),
+            "plane" => self.parse_plane(line, tokens),
+            "disk" => self.parse_disk(line, tokens),
+            "rect" => self.parse_rect(line, tokens),
+            "box" => self.parse_box(line, tokens),
+            "cylinder" | "cone" => self.parse_round(line, directive, tokens),
+            "torus" => self.parse_torus(line, tokens),
+            _ => Err(ParseError::new(
+                line,
+                format!("unknown directive '{}'", directive),
+            )),
+        }
+    }
+
+    ///
+    /// Media give their parameters followed by the boundary shape directive,
+    /// the material of the shape is the phase function
+    ///
+    fn parse_medium(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let split = tokens
+            .iter()
+            .position(|token| !token.contains('='))
+            .unwrap_or(tokens.len());
+        let (tokens, shape) = tokens.split_at(split);
+        let (directive, rest) = match shape.split_first() {
+            Some((directive, rest)) => (*directive, rest),
+            None => {
+                return Err(ParseError::new(
+                    line,
+                    "expected 'medium density=<value> <shape>'".to_string(),
+                ))
+            }
+        };
+        // rays have to enter and leave the boundary once, see ConstantMedium
+        if !matches!(
+            directive,
+            "sphere" | "moving_sphere" | "box" | "cylinder" | "cone"
+        ) {
+            return Err(ParseError::new(
+                line,
+                format!(
+                    "medium boundary has to be a closed convex shape (sphere, moving_sphere, box, cylinder or cone), not '{}'",
+                    directive
+                ),
+            ));
+        }
+        let mut params = Params::parse(line, "medium", tokens)?;
+        let density: f32 = params.required_value("density")?;
+        params.finish()?;
+        if density <= 0.0 {
+            return Err(ParseError::new(
+                line,
+                "medium density has to be positive".to_string(),
+            ));
+        }
+        self.parse_shape(line, directive, rest)?;
+        let boundary = self.shapes.pop().expect("shape parsed");
+        self.shapes.push(ShapeDesc::Medium {
+            boundary: Box::new(boundary),
+            density,
+        });
+        Ok(())
+    }
+
+    fn parse_sphere(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "sphere", tokens)?;
+        let sphere = ShapeDesc::Sphere {
+            center: params.required_vector("center")?,
+            radius: params.required_value("radius")?,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(sphere);
+        Ok(())
+    }
+
+    fn parse_moving_sphere(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "moving_sphere", tokens)?;
+        let sphere = ShapeDesc::MovingSphere {
+            from: params.required_vector("from")?,
+            to: params.required_vector("to")?,
+            radius: params.required_value("radius")?,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(sphere);
+        Ok(())
+    }
+
+    fn parse_triangle(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "triangle", tokens)?;
+        let triangle = ShapeDesc::Triangle {
+            vertices: [
+                params.required_vector("a")?,
+                params.required_vector("b")?,
+                params.required_vector("c")?,
+            ],
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(triangle);
+        Ok(())
+    }
+
+    fn parse_plane(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "plane", tokens)?;
+        let plane = ShapeDesc::Plane {
+            point: params.required_vector("point")?,
+            normal: params.required_normal("normal")?,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(plane);
+        Ok(())
+    }
+
+    fn parse_disk(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "disk", tokens)?;
+        let disk = ShapeDesc::Disk {
+            center: params.required_vector("center")?,
+            normal: params.required_normal("normal")?,
+            radius: params.required_value("radius")?,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(disk);
+        Ok(())
+    }
+
+    ///
+    /// Rectangles give a min,max range for two axes and a single position for the third one,
+    /// which is the axis of the normal
+    ///
+    fn parse_rect(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "rect", tokens)?;
+        let mut ranges = Vec::new();
+        let mut position = None;
+        for (axis, key) in ["x", "y", "z"].iter().enumerate() {
+            let text = params.required_text(key)?;
+            let numbers: Vec<Option<f32>> = text.split(',').map(|n| n.parse().ok()).collect();
+            match numbers.as_slice() {
+                [Some(k)] if position.is_none() => position = Some((axis, *k)),
+                [Some(min), Some(max)] => ranges.push((*min, *max)),
+                _ => {
+                    return Err(ParseError::new(
+                        line,
+                        format!("invalid value '{}' for {}, expected min,max", text, key),
+                    ))
+                }
+            }
+        }
+        let (axis, k) = match position {
+            Some(position) => position,
+            None => {
+                return Err(ParseError::new(
+                    line,
+                    "rect needs a single position for one of x, y or z".to_string(),
+                ))
+            }
+        };
+        let rect = ShapeDesc::Rect {
+            axis,
+            min: (ranges[0].0, ranges[1].0),
+            max: (ranges[0].1, ranges[1].1),
+            k,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(rect);
+        Ok(())
+    }
+
+    fn parse_box(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "box", tokens)?;
+        let min = params.required_vector("min")?;
+        let max = params.required_vector("max")?;
+        if (0..3).any(|axis| min.axis(axis) == max.axis(axis)) {
+            return Err(ParseError::new(
+                line,
+                "box size can't be 0 along any axis".to_string(),
+            ));
+        }
+        let cuboid = ShapeDesc::Box {
+            min,
+            max,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(cuboid);
+        Ok(())
+    }
+
+    /// Cylinders and cones have the same parameters
+    fn parse_round(
+        &mut self,
+        line: usize,
+        directive: &str,
+        tokens: &[&str],
+    ) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, directive, tokens)?;
+        let base = params.required_vector("base")?;
+        let radius = params.required_value("radius")?;
+        let height = params.required_value("height")?;
+        if height == 0.0 {
+            return Err(ParseError::new(
+                line,
+                format!("{} height can't be 0", directive),
+            ));
+        }
+        let material = self.material_name(&mut params)?;
+        params.finish()?;
+        self.shapes.push(if directive == "cone" {
+            ShapeDesc::Cone {
+                base,
+                radius,
+                height,
+                material,
+            }
+        } else {
+            ShapeDesc::Cylinder {
+                base,
+                radius,
+                height,
+                material,
+            }
+        });
+        Ok(())
+    }
+
+    fn parse_torus(&mut self, line: usize, tokens: &[&str]) -> Result<(), ParseError> {
+        let mut params = Params::parse(line, "torus", tokens)?;
+        let torus = ShapeDesc::Torus {
+            center: params.required_vector("center")?,
+            major_radius: params.required_value("major")?,
+            minor_radius: params.required_value("minor")?,
+            material: self.material_name(&mut params)?,
+        };
+        params.finish()?;
+        self.shapes.push(torus);
+        Ok(())
+    }
+
+    ///
+    /// Dielectric parameters, the index of refraction is only optional for dispersive materials
+    /// (taken at 587.6 nm)
+    ///
+    fn dielectric(&self, params: &mut Params) -> Result<MaterialDesc, ParseError> {
+        let cauchy = params.numbers("cauchy", 2)?;
+        let sellmeier = params.numbers("sellmeier", 6)?;
+        let dispersion = match (cauchy, sellmeier) {
+            (Some(_), Some(_)) => {
+                return Err(ParseError::new(
+                    params.line,
+                    "give either cauchy or sellmeier coefficients".to_string(),
+                ))
+            }
+            (Some(a_b), None) => Some(Dispersion::Cauchy {
+                a: a_b[0],
+                b: a_b[1],
+            }),
+            (None, Some(b_c)) => Some(Dispersion::Sellmeier {
+                b: [b_c[0], b_c[1], b_c[2]],
+                c: [b_c[3], b_c[4], b_c[5]],
+            }),
+            (None, None) => None,
+        };
+        // dispersive materials get their ior from the coefficients
+        let ior = match (params.value("ior")?, &dispersion) {
+            (Some(_), Some(_)) => {
+                return Err(ParseError::new(
+                    params.line,
+                    "give either an ior or dispersion coefficients".to_string(),
+                ))
+            }
+            (Some(ior), None) => ior,
+            (None, Some(dispersion)) => Dialectric::dispersive(*dispersion).ior(),
+            (None, None) => return Err(params.missing("ior")),
+        };
+        let exterior_ior = params.value("exterior")?.unwrap_or(1.0);
+        if ior <= 0.0 || exterior_ior <= 0.0 {
+            return Err(ParseError::new(
+                params.line,
+                "dielectric ior has to be positive".to_string(),
+            ));
+        }
+        let tint = match params.rgb("tint")? {
+            Some(color) => Some((color, params.value("tint_distance")?.unwrap_or(1.0))),
+            None => None,
+        };
+        Ok(MaterialDesc::Dielectric {
+            ior,
+            exterior_ior,
+            tint,
+            dispersion,
+            thin: params.value("thin")?,
+        })
+    }
+
+    /// Retrieve the albedo color or texture, making sure the texture was declared
+    fn albedo(&self, params: &mut Params) -> Result<AlbedoDesc, ParseError> {
+        let name = match params.text("texture") {
+            Some(name) => name,
+            None => return Ok(AlbedoDesc::Color(params.required_rgb("albedo")?)),
+        };
+        if params.text("albedo").is_some() {
+            return Err(ParseError::new(
+                params.line,
+                "give either an albedo or a texture".to_string(),
+            ));
+        }
+        if self.texture(name).is_none() {
+            return Err(ParseError::new(
+                params.line,
+                format!("unknown texture '{}'", name),
+            ));
+        }
+        Ok(AlbedoDesc::Texture(name.to_string()))
+    }
+
+    /// Retrieve the material parameter making sure it was declared
+    fn material_name(&self, params: &mut Params) -> Result<String, ParseError> {
+        let name = params.required_text("material")?;
+        if self.material(name).is_none() {
+            return Err(ParseError::new(
+                params.line,
+                format!("unknown material '{}'", name),
+            ));
+        }
+        Ok(name.to_string())
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Write the scene back in the text format read by Scene::parse
+///
+impl fmt::Display for Scene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let settings = &self.settings;
+        write!(
+            f,
+            "settings width={} height={} samples={} depth={} gamma={} threads={} tile={} seed={}",
+            settings.width,
+            settings.height,
+            settings.samples_per_pixel,
+            settings.max_depth,
+            settings.gamma,
+            settings.threads,
+            settings.tile_size,
+            settings.seed
+        )?;
+        if settings.spectral {
+            write!(f, " spectral=true")?;
+        }
+        writeln!(f)?;
+        match &settings.background {
+            Background::Solid(color) if color.is_black() => writeln!(f, "background black")?,
+            Background::Solid(color) => writeln!(f, "background solid color={}", rgb(color))?,
+            Background::Gradient { bottom, top } => writeln!(
+                f,
+                "background gradient bottom={} top={}",
+                rgb(bottom),
+                rgb(top)
+            )?,
+        }
+        let camera = &self.camera;
+        write!(
+            f,
+            "camera from={} at={} up={} fov={} aperture={}",
+            vector(&camera.look_from),
+            vector(&camera.look_at),
+            vector(&camera.vup),
+            camera.fov,
+            camera.aperture
+        )?;
+        if let Some(focus_dist) = camera.focus_dist {
+            write!(f, " focus={}", focus_dist)?;
+        }
+        if camera.shutter != (0.0, 0.0) {
+            write!(f, " shutter={},{}", camera.shutter.0, camera.shutter.1)?;
+        }
+        writeln!(f)?;
+        for (name, texture) in &self.textures {
+            match texture {
+                TextureDesc::Solid { color } => {
+                    writeln!(f, "texture {} solid color={}", name, rgb(color))?
+                }
+                TextureDesc::Checker { even, odd, scale } => writeln!(
+                    f,
+                    "texture {} checker even={} odd={} scale={}",
+                    name,
+                    rgb(even),
+                    rgb(odd),
+                    scale
+                )?,
+                TextureDesc::Image { file } => writeln!(f, "texture {} image file={}", name, file)?,
+                TextureDesc::Noise {
+                    pattern,
+                    scale,
+                    seed,
+                    octaves,
+                    low,
+                    high,
+                } => writeln!(
+                    f,
+                    "texture {} noise pattern={} scale={} seed={} octaves={} low={} high={}",
+                    name,
+                    pattern.name(),
+                    scale,
+                    seed,
+                    octaves,
+                    rgb(low),
+                    rgb(high)
+                )?,
+            }
+        }
+        for (name, material) in &self.materials {
+            match material {
+                MaterialDesc::Lambertian { albedo: desc } => {
+                    writeln!(f, "material {} lambertian {}", name, albedo(desc))?
+                }
+                MaterialDesc::Metal { albedo: desc, fuzz } => {
+                    write!(f, "material {} metal {}", name, albedo(desc))?;
+                    if let Some(fuzz) = fuzz {
+                        write!(f, " fuzz={}", fuzz)?;
+                    }
+                    writeln!(f)?
+                }
+                MaterialDesc::Dielectric {
+                    ior,
+                    exterior_ior,
+                    tint,
+                    dispersion,
+                    thin,
+                } => {
+                    write!(f, "material {} dielectric", name)?;
+                    if dispersion.is_none() {
+                        write!(f, " ior={}", ior)?;
+                    }
+                    if *exterior_ior != 1.0 {
+                        write!(f, " exterior={}", exterior_ior)?;
+                    }
+                    if let Some((color, distance)) = tint {
+                        write!(f, " tint={} tint_distance={}", rgb(color), distance)?;
+                    }
+                    match dispersion {
+                        Some(Dispersion::Cauchy { a, b }) => write!(f, " cauchy={},{}", a, b)?,
+                        Some(Dispersion::Sellmeier { b, c }) => write!(
+                            f,
+                            " sellmeier={},{},{},{},{},{}",
+                            b[0], b[1], b[2], c[0], c[1], c[2]
+                        )?,
+                        None => {}
+                    }
+                    if let Some(thickness) = thin {
+                        write!(f, " thin={}", thickness)?;
+                    }
+                    writeln!(f)?
+                }
+                MaterialDesc::Light { emit } => {
+                    writeln!(f, "material {} light emit={}", name, rgb(emit))?
+                }
+                MaterialDesc::Isotropic { albedo: desc } => {
+                    writeln!(f, "material {} isotropic {}", name, albedo(desc))?
+                }
+                MaterialDesc::Principled {
+                    albedo: desc,
+                    metallic,
+                    roughness,
+                    specular,
+                } => writeln!(
+                    f,
+                    "material {} principled {} metallic={} roughness={} specular={}",
+                    name,
+                    albedo(desc),
+                    metallic,
+                    roughness,
+                    specular
+                )?,
+            }
+        }
+        for shape in &self.shapes {
+            writeln!(f, "{}", shape)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Write the shape directive, as read by Scene::parse
+///
+impl fmt::Display for ShapeDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShapeDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => write!(
+                f,
+                "sphere center={} radius={} material={}",
+                vector(center),
+                radius,
+                material
+            ),
+            ShapeDesc::MovingSphere {
+                from,
+                to,
+                radius,
+                material,
+            } => write!(
+                f,
+                "moving_sphere from={} to={} radius={} material={}",
+                vector(from),
+                vector(to),
+                radius,
+                material
+            ),
+            ShapeDesc::Triangle {
+                vertices: [a, b, c],
+                material,
+            } => write!(
+                f,
+                "triangle a={} b={} c={} material={}",
+                vector(a),
+                vector(b),
+                vector(c),
+                material
+            ),
+            ShapeDesc::Plane {
+                point,
+                normal,
+                material,
+            } => write!(
+                f,
+                "plane point={} normal={} material={}",
+                vector(point),
+                vector(normal),
+                material
+            ),
+            ShapeDesc::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => write!(
+                f,
+                "disk center={} normal={} radius={} material={}",
+                vector(center),
+                vector(normal),
+                radius,
+                material
+            ),
+            ShapeDesc::Rect {
+                axis,
+                min,
+                max,
+                k,
+                material,
+            } => {
+                let mut ranges = vec![(min.0, max.0), (min.1, max.1)].into_iter();
+                write!(f, "rect")?;
+                for (index, key) in ["x", "y", "z"].iter().enumerate() {
+                    if index == *axis {
+                        write!(f, " {}={}", key, k)?;
+                    } else if let Some((min, max)) = ranges.next() {
+                        write!(f, " {}={},{}", key, min, max)?;
+                    }
+                }
+                write!(f, " material={}", material)
+            }
+            ShapeDesc::Box { min, max, material } => write!(
+                f,
+                "box min={} max={} material={}",
+                vector(min),
+                vector(max),
+                material
+            ),
+            ShapeDesc::Cylinder {
+                base,
+                radius,
+                height,
+                material,
+            } => write!(
+                f,
+                "cylinder base={} radius={} height={} material={}",
+                vector(base),
+                radius,
+                height,
+                material
+            ),
+            ShapeDesc::Cone {
+                base,
+                radius,
+                height,
+                material,
+            } => write!(
+                f,
+                "cone base={} radius={} height={} material={}",
+                vector(base),
+                radius,
+                height,
+                material
+            ),
+            ShapeDesc::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                material,
+            } => write!(
+                f,
+                "torus center={} major={} minor={} material={}",
+                vector(center),
+                major_radius,
+                minor_radius,
+                material
+            ),
+            ShapeDesc::Medium { boundary, density } => {
+                write!(f, "medium density={} {}", density, boundary)
+            }
+        }
+    }
+}
+
+fn vector(v: &Vec3D) -> String {
+    format!("{},{},{}", v.x(), v.y(), v.z())
+}
+
+fn rgb(color: &Rgb) -> String {
+    format!("{},{},{}", color.r(), color.g(), color.b())
+}
+
+fn albedo(albedo: &AlbedoDesc) -> String {
+    match albedo {
+        AlbedoDesc::Color(color) => format!("albedo={}", rgb(color)),
+        AlbedoDesc::Texture(name) => format!("texture={}", name),
+    }
+}
+
+///
+/// key=value parameters of a directive.
+/// Parameters are removed as they're read so that finish() can report the unknown ones.
+///
+struct Params<'a> {
+    line: usize,
+    directive: &'a str,
+    pairs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Params<'a> {
+    fn parse(line: usize, directive: &'a str, tokens: &[&'a str]) -> Result<Self, ParseError> {
+        let mut pairs: Vec<(&str, &str)> = Vec::new();
+        for token in tokens {
+            let (key, value) = match token.find('=') {
+                Some(index) => (&token[..index], &token[index + 1..]),
+                None => {
+                    return Err(ParseError::new(
+                        line,
+                        format!("expected key=value but found '{}'", token),
+                    ))
+                }
+            };
+            if pairs.iter().any(|(k, _)| *k == key) {
+                return Err(ParseError::new(
+                    line,
+                    format!("parameter '{}' given twice", key),
+                ));
+            }
+            pairs.push((key, value));
+        }
+        Ok(Self {
+            line,
+            directive,
+            pairs,
+        })
+    }
+
+    fn text(&mut self, key: &str) -> Option<&'a str> {
+        let index = self.pairs.iter().position(|(k, _)| *k == key)?;
+        Some(self.pairs.remove(index).1)
+    }
+
+    fn missing(&self, key: &str) -> ParseError {
+        ParseError::new(
+            self.line,
+            format!("missing parameter '{}' for {}", key, self.directive),
+        )
+    }
+
+    fn required_text(&mut self, key: &str) -> Result<&'a str, ParseError> {
+        match self.text(key) {
+            Some(text) => Ok(text),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    fn value<T: FromStr>(&mut self, key: &str) -> Result<Option<T>, ParseError> {
+        match self.text(key) {
+            Some(text) => match text.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(ParseError::new(
+                    self.line,
+                    format!("invalid value '{}' for {}", text, key),
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn required_value<T: FromStr>(&mut self, key: &str) -> Result<T, ParseError> {
+        match self.value(key)? {
+            Some(value) => Ok(value),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    fn triple(&mut self, key: &str) -> Result<Option<(f32, f32, f32)>, ParseError> {
+        let text = match self.text(key) {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let numbers: Vec<Option<f32>> = text.split(',').map(|n| n.parse().ok()).collect();
+        match numbers.as_slice() {
+            [Some(x), Some(y), Some(z)] => Ok(Some((*x, *y, *z))),
+            _ => Err(ParseError::new(
+                self.line,
+                format!("invalid value '{}' for {}, expected x,y,z", text, key),
+            )),
+        }
+    }
+
+    /// Comma separated list of exactly count numbers
+    fn numbers(&mut self, key: &str, count: usize) -> Result<Option<Vec<f32>>, ParseError> {
+        let text = match self.text(key) {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let numbers: Option<Vec<f32>> = text.split(',').map(|n| n.parse().ok()).collect();
+        match numbers {
+            Some(numbers) if numbers.len() == count => Ok(Some(numbers)),
+            _ => Err(ParseError::new(
+                self.line,
+                format!(
+                    "invalid value '{}' for {}, expected {} numbers",
+                    text, key, count
+                ),
+            )),
+        }
+    }
+
+    /// Pair of numbers given as min,max
+    fn range(&mut self, key: &str) -> Result<Option<(f32, f32)>, ParseError> {
+        let text = match self.text(key) {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let numbers: Vec<Option<f32>> = text.split(',').map(|n| n.parse().ok()).collect();
+        match numbers.as_slice() {
+            [Some(min), Some(max)] => Ok(Some((*min, *max))),
+            _ => Err(ParseError::new(
+                self.line,
+                format!("invalid value '{}' for {}, expected min,max", text, key),
+            )),
+        }
+    }
+
+    fn vector(&mut self, key: &str) -> Result<Option<Vec3D>, ParseError> {
+        Ok(self.triple(key)?.map(|(x, y, z)| Vec3D::new(x, y, z)))
+    }
+
+    fn required_vector(&mut self, key: &str) -> Result<Vec3D, ParseError> {
+        match self.vector(key)? {
+            Some(vector) => Ok(vector),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    /// Direction which can be normalized (not zero)
+    fn required_normal(&mut self, key: &str) -> Result<Vec3D, ParseError> {
+        let normal = self.required_vector(key)?;
+        if normal.squared_length() == 0.0 {
+            return Err(ParseError::new(self.line, format!("{} can't be zero", key)));
+        }
+        Ok(normal)
+    }
+
+    fn rgb(&mut self, key: &str) -> Result<Option<Rgb>, ParseError> {
+        Ok(self.triple(key)?.map(|(r, g, b)| Rgb::new(r, g, b)))
+    }
+
+    fn required_rgb(&mut self, key: &str) -> Result<Rgb, ParseError> {
+        match self.triple(key)? {
+            Some((r, g, b)) => Ok(Rgb::new(r, g, b)),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    /// Make sure all the parameters were used
+    fn finish(self) -> Result<(), ParseError> {
+        match self.pairs.first() {
+            Some((key, _)) => Err(ParseError::new(
+                self.line,
+                format!("unknown parameter '{}' for {}", key, self.directive),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ParseError {
+    pub fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "{}", error),
+            SceneError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(error: std::io::Error) -> Self {
+        SceneError::Io(error)
+    }
+}
+
+impl From<ParseError> for SceneError {
+    fn from(error: ParseError) -> Self {
+        SceneError::Parse(error)
+    }
+}
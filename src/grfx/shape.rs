@@ -1,18 +1,24 @@
-use crate::grfx::color::Color;
+use crate::grfx::aabb::Aabb;
+use crate::grfx::color::Rgb;
 use crate::grfx::material::Lambertian;
 use crate::grfx::material::Material;
 use crate::grfx::ray::Ray;
+use crate::grfx::sampling;
 use crate::grfx::vector::Vec3D;
-use std::rc::Rc;
+use rand::{Rng, RngCore};
+use std::sync::Arc;
 
 /// Hit records which contains the point being hit and the constant hitting it as well as its normal
+/// (u, v) are the surface coordinates of the point, both in [0, 1], used to map textures/lights
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct HitRecord {
     pub t: f32,
     pub point: Vec3D,
     pub normal: Vec3D,
-    pub material: Rc<dyn Material>,
+    pub u: f32,
+    pub v: f32,
+    pub material: Arc<dyn Material>,
 }
 impl HitRecord {
     pub fn new() -> Self {
@@ -20,7 +26,9 @@ impl HitRecord {
             t: 0.0,
             point: Vec3D::new(0.0, 0.0, 0.0),
             normal: Vec3D::new(0.0, 0.0, 0.0),
-            material: Rc::new(Lambertian::new(Color::rgb(255, 255, 255))),
+            u: 0.0,
+            v: 0.0,
+            material: Arc::new(Lambertian::new(Rgb::WHITE)),
         }
     }
 }
@@ -32,10 +40,10 @@ impl HitRecord {
 pub struct Sphere {
     center: Vec3D,
     radius: f32,
-    material: Rc<dyn Material>,
+    material: Arc<dyn Material>,
 }
 impl Sphere {
-    pub fn new(center: Vec3D, radius: f32, material: Rc<dyn Material>) -> Self {
+    pub fn new(center: Vec3D, radius: f32, material: Arc<dyn Material>) -> Self {
         Self {
             center,
             radius,
@@ -52,71 +60,235 @@ impl Sphere {
 
 impl Hitable for Sphere {
     fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
-        // get components in the form of ax^2 + bx + c
-        // Equation for a sphere is x^2 + y^2 + z^2 -R^2 = 0 -> x*x + y*y + z*z = R*R
-        // If sphere center is recepresented by (cx, cy, cz) then
-        // (x-cx)*(x-cx)  + (y-cy)*(y-cy) + (z-cz)*(z-cz)
-        // this is the same as saying dot product since each element is multiplied by its corresponding match
-        // dot[(point - center), (point-center)]
-        // (point-center) . (point - center)
-        // the point is given but the point/direction according to the ray theory is A + tB (origin + constant * point) and the center of the sphere we know
-        // this becomes (A + tB- C) . (A + tB - C) = R*R
-        // we can simplify:
-        //              ((A-C) + tB). ((A-C) + tB)
-        //              (A-C).(A-C) + 2*tB.(A-C)  + t*t*B.B = R*R
-        // re-ordering gives
-        // t*tB.B + 2*tB.(A-C) + (A-C).(A-C) which looks an awful lot like a polynomial of the form ax^2 + bx + c
-        // we make it all = 0 and solve for the coefficients using quadratic formula
-        //  t*tB.B + 2*tB.(A-C) + (A-C).(A-C) - R.R = 0
-        // a = B.B
-        // b = 2B.(A-C)
-        // c = (A-C).(A-C) - R.R
-        let origin_center = ray.origin() - self.center; // this A-C on the equauation above
-        let a = Vec3D::dot(&ray.direction(), &ray.direction());
-        let b = 2.0 * Vec3D::dot(&ray.direction(), &origin_center);
-        let c = Vec3D::dot(&origin_center, &origin_center) - self.radius * self.radius;
-
-        // let's calculate the zeros for this equation
-        let discriminant = b * b - 4.0 * a * c; // b^2 - 4ac under the sqrt()
-
-        // got a real hit
-        if discriminant > 0.0 {
-            let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
-            let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
-
-            // our t1 calculated value is within range?
-            if t1 < max && t1 > min {
-                record.t = t1;
-                record.point = ray.point_at(t1);
-                record.normal = (record.point - self.center) / self.radius;
-                record.material = self.material.clone();
-                return true;
-            }
+        hit_sphere(
+            &self.center,
+            self.radius,
+            &self.material,
+            ray,
+            min,
+            max,
+            record,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3D::new(self.radius, self.radius, self.radius);
+        let radius = Vec3D::max(&radius, &-radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    /// Directions are sampled uniformly in the cone the sphere covers seen from the origin
+    fn pdf_value(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f32::MAX, &mut record) {
+            return 0.0;
+        }
+        match cone_cosine(&self.center, self.radius, origin) {
+            Some(cos_theta_max) => sampling::uniform_cone_pdf(cos_theta_max),
+            None => 0.0,
+        }
+    }
+
+    fn sample_direction(&self, origin: &Vec3D, rng: &mut dyn RngCore) -> Option<Vec3D> {
+        let cos_theta_max = cone_cosine(&self.center, self.radius, origin)?;
+        let axis = (self.center - *origin).unit_vector();
+        Some(axis.local_to_world(&sampling::uniform_cone(cos_theta_max, rng)))
+    }
+}
+
+///
+/// Cosine of the half angle of the cone a sphere covers seen from origin,
+/// None from inside the sphere
+///
+fn cone_cosine(center: &Vec3D, radius: f32, origin: &Vec3D) -> Option<f32> {
+    let distance_squared = (*center - *origin).squared_length();
+    let ratio = radius * radius / distance_squared;
+    if ratio >= 1.0 || !ratio.is_finite() {
+        return None;
+    }
+    Some((1.0 - ratio).sqrt())
+}
+
+///
+/// Density per solid angle of sampling a point uniformly over a surface of the given area,
+/// seen t along direction where the surface has the given normal
+///
+fn area_pdf(t: f32, direction: &Vec3D, normal: &Vec3D, area: f32) -> f32 {
+    let length = direction.length();
+    let cosine = (Vec3D::dot(direction, normal) / length).abs();
+    if cosine < 1e-6 || area <= 0.0 {
+        return 0.0;
+    }
+    let distance = t * length;
+    distance * distance / (cosine * area)
+}
+
+///
+/// Hit a sphere with the given center and radius, shared by Sphere and MovingSphere
+///
+fn hit_sphere(
+    center: &Vec3D,
+    radius: f32,
+    material: &Arc<dyn Material>,
+    ray: &Ray,
+    min: f32,
+    max: f32,
+    record: &mut HitRecord,
+) -> bool {
+    // get components in the form of ax^2 + bx + c
+    // Equation for a sphere is x^2 + y^2 + z^2 -R^2 = 0 -> x*x + y*y + z*z = R*R
+    // If sphere center is recepresented by (cx, cy, cz) then
+    // (x-cx)*(x-cx)  + (y-cy)*(y-cy) + (z-cz)*(z-cz)
+    // this is the same as saying dot product since each element is multiplied by its corresponding match
+    // dot[(point - center), (point-center)]
+    // (point-center) . (point - center)
+    // the point is given but the point/direction according to the ray theory is A + tB (origin + constant * point) and the center of the sphere we know
+    // this becomes (A + tB- C) . (A + tB - C) = R*R
+    // we can simplify:
+    //              ((A-C) + tB). ((A-C) + tB)
+    //              (A-C).(A-C) + 2*tB.(A-C)  + t*t*B.B = R*R
+    // re-ordering gives
+    // t*tB.B + 2*tB.(A-C) + (A-C).(A-C) which looks an awful lot like a polynomial of the form ax^2 + bx + c
+    // we make it all = 0 and solve for the coefficients using quadratic formula
+    //  t*tB.B + 2*tB.(A-C) + (A-C).(A-C) - R.R = 0
+    // a = B.B
+    // b = 2B.(A-C)
+    // c = (A-C).(A-C) - R.R
+    let origin_center = ray.origin() - *center; // this A-C on the equauation above
+    let a = Vec3D::dot(&ray.direction(), &ray.direction());
+    let b = 2.0 * Vec3D::dot(&ray.direction(), &origin_center);
+    let c = Vec3D::dot(&origin_center, &origin_center) - radius * radius;
+
+    // let's calculate the zeros for this equation
+    let discriminant = b * b - 4.0 * a * c; // b^2 - 4ac under the sqrt()
+
+    // got a real hit
+    if discriminant > 0.0 {
+        let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
+        let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
 
-            // our t2 calculated value is within range?
-            if t2 < max && t2 > min {
-                record.t = t2;
-                record.point = ray.point_at(t2);
-                record.normal = (record.point - self.center) / self.radius;
-                record.material = self.material.clone();
+        // our t1 or t2 calculated value is within range?
+        for t in [t1, t2] {
+            if t < max && t > min {
+                record.t = t;
+                record.point = ray.point_at(t);
+                record.normal = (record.point - *center) / radius;
+                let (u, v) = sphere_uv(&((record.point - *center) / radius.abs()));
+                record.u = u;
+                record.v = v;
+                record.material = material.clone();
                 return true;
             }
         }
-        false
     }
+    false
 }
+
+///
+/// Moving sphere structure
+///     center0 center at time0
+///     center1 center at time1
+///     radius
+/// The center moves in a straight line, it stays at center0 before time0 and at center1 after time1.
+/// Used for motion blur, see Camera::with_shutter.
+///
+pub struct MovingSphere {
+    center0: Vec3D,
+    center1: Vec3D,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3D,
+        time0: f32,
+        center1: Vec3D,
+        time1: f32,
+        radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    /// Center at the given time, the sphere stays at its ends before time0 and after time1
+    pub fn center(&self, time: f32) -> Vec3D {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let fraction = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + fraction * (self.center1 - self.center0)
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Hitable for MovingSphere {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let center = self.center(ray.time());
+        hit_sphere(&center, self.radius, &self.material, ray, min, max, record)
+    }
+
+    /// Box around the sphere between time0 and time1
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3D::new(self.radius, self.radius, self.radius);
+        let radius = Vec3D::max(&radius, &-radius);
+        let start = Aabb::new(self.center0 - radius, self.center0 + radius);
+        let end = Aabb::new(self.center1 - radius, self.center1 + radius);
+        Some(Aabb::surrounding(&start, &end))
+    }
+}
+
+///
+/// Surface coordinates of a point on the unit sphere (given as the direction from the center)
+/// u goes around the y axis starting at -x, v goes from the bottom (-y) to the top (+y)
+///
+fn sphere_uv(direction: &Vec3D) -> (f32, f32) {
+    let theta = (-direction.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-direction.z()).atan2(direction.x()) + std::f32::consts::PI;
+    (
+        phi / (2.0 * std::f32::consts::PI),
+        theta / std::f32::consts::PI,
+    )
+}
+
 /// Interface to be used by all hittable structures
 /// The trait function hit shoudl be implemented by all shapes.
+/// bounding_box returns the box enclosing the shape, or None for infinite shapes.
+/// Hitables are shared between render threads, hence Send + Sync.
+///
+/// Shapes which can be used as lights (see Renderer::with_lights) also define:
+///     sample_direction -> random direction from origin towards the shape, None if it can't be sampled
+///     pdf_value -> probability density (per solid angle) of sample_direction giving direction,
+///                  0 when the direction misses the shape
 #[allow(dead_code)]
-pub trait Hitable {
+pub trait Hitable: Send + Sync {
     fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool;
+    fn bounding_box(&self) -> Option<Aabb>;
+    fn pdf_value(&self, _origin: &Vec3D, _direction: &Vec3D) -> f32 {
+        0.0
+    }
+    fn sample_direction(&self, _origin: &Vec3D, _rng: &mut dyn RngCore) -> Option<Vec3D> {
+        None
+    }
 }
 
 /// Array like structure that contains all hittable objects shown
 /// It implements hittable in order to check all objects being hit at once
 #[allow(dead_code)]
 pub struct HitList {
-    hitable_items: Vec<Rc<dyn Hitable>>,
+    hitable_items: Vec<Arc<dyn Hitable>>,
 }
 impl HitList {
     pub fn new() -> Self {
@@ -124,12 +296,16 @@ impl HitList {
             hitable_items: Vec::new(),
         }
     }
-    pub fn add(&mut self, item: Rc<dyn Hitable>) {
+    pub fn add(&mut self, item: Arc<dyn Hitable>) {
         &self.hitable_items.push(item);
     }
-    pub fn get(&self, index: usize) -> Option<&Rc<dyn Hitable>> {
+    pub fn get(&self, index: usize) -> Option<&Arc<dyn Hitable>> {
         self.hitable_items.get(index)
     }
+    /// Consume the list and retrieve all its items
+    pub fn into_items(self) -> Vec<Arc<dyn Hitable>> {
+        self.hitable_items
+    }
 }
 impl Hitable for HitList {
     fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
@@ -140,12 +316,1074 @@ impl Hitable for HitList {
             if ray_hit.hit(ray, min, closest_so_far, &mut temp_record) {
                 hit_anything = true;
                 closest_so_far = temp_record.t;
-                record.point = temp_record.point;
-                record.t = temp_record.t;
-                record.normal = temp_record.normal;
-                record.material = temp_record.material.clone();
+                *record = temp_record.clone();
             }
         }
         hit_anything
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut bbox = Aabb::empty();
+        for item in &self.hitable_items {
+            bbox = Aabb::surrounding(&bbox, &item.bounding_box()?);
+        }
+        Some(bbox)
+    }
+
+    /// Items are picked with the same probability, the density is their average
+    fn pdf_value(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        if self.hitable_items.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self
+            .hitable_items
+            .iter()
+            .map(|item| item.pdf_value(origin, direction))
+            .sum();
+        sum / self.hitable_items.len() as f32
+    }
+
+    fn sample_direction(&self, origin: &Vec3D, rng: &mut dyn RngCore) -> Option<Vec3D> {
+        if self.hitable_items.is_empty() {
+            return None;
+        }
+        let index = rng.gen_range(0..self.hitable_items.len());
+        self.hitable_items[index].sample_direction(origin, rng)
+    }
+}
+
+///
+/// Triangle structure
+///     vertices (counter clockwise when looking at the front face)
+///     normals at each vertex, interpolated over the surface (smooth shading), optional
+///     uvs texture coordinates at each vertex
+/// Implements Hitable using the Möller–Trumbore intersection algorithm.
+/// Degenerate (zero area) triangles are never hit.
+///
+pub struct Triangle {
+    vertices: [Vec3D; 3],
+    normals: Option<[Vec3D; 3]>,
+    uvs: [(f32, f32); 3],
+    normal: Vec3D,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    /// Flat triangle, uvs default to (0, 0), (1, 0) and (0, 1)
+    pub fn new(a: Vec3D, b: Vec3D, c: Vec3D, material: Arc<dyn Material>) -> Self {
+        let cross = Vec3D::cross(&(b - a), &(c - a));
+        let normal = if cross.squared_length() > 0.0 {
+            cross.unit_vector()
+        } else {
+            cross
+        };
+        Self {
+            vertices: [a, b, c],
+            normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            normal,
+            material,
+        }
+    }
+
+    /// Use the normals given at each vertex instead of the flat face normal
+    pub fn with_normals(mut self, normals: [Vec3D; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Use the texture coordinates given at each vertex
+    pub fn with_uvs(mut self, uvs: [(f32, f32); 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn vertices(&self) -> &[Vec3D; 3] {
+        &self.vertices
+    }
+
+    /// Face (geometric) normal, zero for degenerate triangles
+    pub fn normal(&self) -> Vec3D {
+        self.normal
+    }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        // Solve origin + t * direction = a + u * (b - a) + v * (c - a) with Cramer's rule
+        let [a, b, c] = self.vertices;
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let direction = ray.direction();
+        let p = Vec3D::cross(&direction, &edge2);
+        let determinant = Vec3D::dot(&edge1, &p);
+        // the determinant vanishes for rays parallel to the plane (grazing) and degenerate
+        // triangles, compare it relative to the sizes involved so any scale works
+        let scale = edge1.length() * edge2.length() * direction.length();
+        if determinant.abs() <= scale * 1e-7 || !determinant.is_finite() {
+            return false;
+        }
+        let inverse = 1.0 / determinant;
+        let s = ray.origin() - a;
+        let u = Vec3D::dot(&s, &p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return false;
+        }
+        let q = Vec3D::cross(&s, &edge1);
+        let v = Vec3D::dot(&direction, &q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return false;
+        }
+        let t = Vec3D::dot(&edge2, &q) * inverse;
+        if t >= max || t <= min {
+            return false;
+        }
+
+        let w = 1.0 - u - v;
+        record.t = t;
+        record.point = ray.point_at(t);
+        record.normal = match &self.normals {
+            Some([na, nb, nc]) => {
+                let normal = w * na + u * nb + v * nc;
+                if normal.squared_length() > 0.0 {
+                    normal.unit_vector()
+                } else {
+                    self.normal
+                }
+            }
+            None => self.normal,
+        };
+        let [uv_a, uv_b, uv_c] = self.uvs;
+        record.u = w * uv_a.0 + u * uv_b.0 + v * uv_c.0;
+        record.v = w * uv_a.1 + u * uv_b.1 + v * uv_c.1;
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [a, b, c] = self.vertices;
+        let bbox = Aabb::new(a, a).grow(b).grow(c);
+        // triangles lying in an axis plane would get a flat box which rays can't hit
+        let padding = Vec3D::new(1e-4, 1e-4, 1e-4);
+        Some(Aabb::new(bbox.min() - padding, bbox.max() + padding))
+    }
+
+    fn pdf_value(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f32::MAX, &mut record) {
+            return 0.0;
+        }
+        let [a, b, c] = self.vertices;
+        let area = 0.5 * Vec3D::cross(&(b - a), &(c - a)).length();
+        // the face normal, interpolated normals don't give the surface orientation
+        area_pdf(record.t, direction, &self.normal, area)
+    }
+
+    fn sample_direction(&self, origin: &Vec3D, rng: &mut dyn RngCore) -> Option<Vec3D> {
+        // uniform point over the triangle: folding the square onto it is avoided by the sqrt
+        let [a, b, c] = self.vertices;
+        let s = rng.gen::<f32>().sqrt();
+        let t = rng.gen::<f32>();
+        let point = (1.0 - s) * a + s * (1.0 - t) * b + s * t * c;
+        Some(point - *origin)
+    }
+}
+
+///
+/// Infinite plane structure
+///     point on the plane
+///     normal of the plane (front face)
+/// The texture coordinates repeat every unit along two directions on the plane.
+/// Planes have no bounding box.
+///
+pub struct Plane {
+    point: Vec3D,
+    normal: Vec3D,
+    material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3D, normal: Vec3D, material: Arc<dyn Material>) -> Self {
+        assert!(
+            normal.squared_length() > 0.0,
+            "Invalid plane normal {:?}",
+            normal
+        );
+        Self {
+            point,
+            normal: normal.unit_vector(),
+            material,
+        }
+    }
+
+    pub fn point(&self) -> &Vec3D {
+        &self.point
+    }
+
+    pub fn normal(&self) -> &Vec3D {
+        &self.normal
+    }
+}
+
+impl Hitable for Plane {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let t = match plane_hit(&self.point, &self.normal, ray) {
+            Some(t) if t < max && t > min => t,
+            _ => return false,
+        };
+        let point = ray.point_at(t);
+        let (tangent, bitangent) = plane_basis(&self.normal);
+        let offset = point - self.point;
+        record.t = t;
+        record.point = point;
+        record.normal = self.normal;
+        record.u = Vec3D::dot(&offset, &tangent).rem_euclid(1.0);
+        record.v = Vec3D::dot(&offset, &bitangent).rem_euclid(1.0);
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+///
+/// Disk structure
+///     center
+///     normal of the disk (front face)
+///     radius
+/// u goes around the center, v from the center (0) to the edge (1).
+///
+pub struct Disk {
+    center: Vec3D,
+    normal: Vec3D,
+    radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3D, normal: Vec3D, radius: f32, material: Arc<dyn Material>) -> Self {
+        assert!(
+            normal.squared_length() > 0.0,
+            "Invalid disk normal {:?}",
+            normal
+        );
+        Self {
+            center,
+            normal: normal.unit_vector(),
+            radius: radius.abs(),
+            material,
+        }
+    }
+
+    pub fn center(&self) -> &Vec3D {
+        &self.center
+    }
+
+    pub fn normal(&self) -> &Vec3D {
+        &self.normal
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let t = match plane_hit(&self.center, &self.normal, ray) {
+            Some(t) if t < max && t > min => t,
+            _ => return false,
+        };
+        let point = ray.point_at(t);
+        let offset = point - self.center;
+        let distance = offset.length();
+        if distance > self.radius {
+            return false;
+        }
+        let (tangent, bitangent) = plane_basis(&self.normal);
+        let angle = Vec3D::dot(&offset, &bitangent).atan2(Vec3D::dot(&offset, &tangent));
+        record.t = t;
+        record.point = point;
+        record.normal = self.normal;
+        record.u = angle / (2.0 * std::f32::consts::PI) + 0.5;
+        record.v = distance / self.radius;
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // along each axis the disk spans radius * sin(angle between the axis and the normal)
+        let extent = |axis: usize| {
+            let cosine = self.normal.axis(axis);
+            self.radius * (1.0 - cosine * cosine).max(0.0).sqrt() + 1e-4
+        };
+        let extent = Vec3D::new(extent(0), extent(1), extent(2));
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn pdf_value(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f32::MAX, &mut record) {
+            return 0.0;
+        }
+        let area = std::f32::consts::PI * self.radius * self.radius;
+        area_pdf(record.t, direction, &self.normal, area)
+    }
+
+    fn sample_direction(&self, origin: &Vec3D, rng: &mut dyn RngCore) -> Option<Vec3D> {
+        let (x, y) = sampling::uniform_disk(rng);
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let point = self.center + self.radius * x * tangent + self.radius * y * bitangent;
+        Some(point - *origin)
+    }
+}
+
+///
+/// Axis aligned rectangle structure
+///     axis of the normal (0 = x, 1 = y, 2 = z), the normal points towards the positive side
+///     min, max corners using the two other axes in order (x, y for the XY plane etc)
+///     k position of the rectangle along the normal axis
+/// (u, v) go from the min (0, 0) to the max (1, 1) corner.
+/// Building block for Cornell boxes and area lights.
+///
+pub struct Rect {
+    axis: usize,
+    min: (f32, f32),
+    max: (f32, f32),
+    k: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Rect {
+    ///
+    /// Rectangle perpendicular to the given axis.
+    /// Panics if the axis is not 0, 1 or 2.
+    ///
+    pub fn new(
+        axis: usize,
+        min: (f32, f32),
+        max: (f32, f32),
+        k: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(axis < 3, "Invalid rectangle axis {}", axis);
+        Self {
+            axis,
+            min: (min.0.min(max.0), min.1.min(max.1)),
+            max: (min.0.max(max.0), min.1.max(max.1)),
+            k,
+            material,
+        }
+    }
+
+    /// Rectangle from (x0, y0) to (x1, y1) at z = k
+    pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Self::new(2, (x0, y0), (x1, y1), k, material)
+    }
+
+    /// Rectangle from (x0, z0) to (x1, z1) at y = k
+    pub fn xz(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Self::new(1, (x0, z0), (x1, z1), k, material)
+    }
+
+    /// Rectangle from (y0, z0) to (y1, z1) at x = k
+    pub fn yz(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Arc<dyn Material>) -> Self {
+        Self::new(0, (y0, z0), (y1, z1), k, material)
+    }
+
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    pub fn min(&self) -> (f32, f32) {
+        self.min
+    }
+
+    pub fn max(&self) -> (f32, f32) {
+        self.max
+    }
+
+    pub fn k(&self) -> f32 {
+        self.k
+    }
+}
+
+impl Hitable for Rect {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let direction = ray.direction().axis(self.axis);
+        if direction == 0.0 {
+            return false;
+        }
+        let t = (self.k - ray.origin().axis(self.axis)) / direction;
+        if t >= max || t <= min {
+            return false;
+        }
+        let point = ray.point_at(t);
+        let (a, b) = other_axes(self.axis);
+        let (pa, pb) = (point.axis(a), point.axis(b));
+        if pa < self.min.0 || pa > self.max.0 || pb < self.min.1 || pb > self.max.1 {
+            return false;
+        }
+        record.t = t;
+        record.point = point;
+        record.normal = unit_axis(self.axis);
+        record.u = (pa - self.min.0) / (self.max.0 - self.min.0);
+        record.v = (pb - self.min.1) / (self.max.1 - self.min.1);
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // pad the flat side so rays can hit the box
+        let (a, b) = other_axes(self.axis);
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        min[self.axis] = self.k - 1e-4;
+        max[self.axis] = self.k + 1e-4;
+        min[a] = self.min.0;
+        max[a] = self.max.0;
+        min[b] = self.min.1;
+        max[b] = self.max.1;
+        Some(Aabb::new(
+            Vec3D::new(min[0], min[1], min[2]),
+            Vec3D::new(max[0], max[1], max[2]),
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        let mut record = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction), 0.001, f32::MAX, &mut record) {
+            return 0.0;
+        }
+        let area = (self.max.0 - self.min.0) * (self.max.1 - self.min.1);
+        area_pdf(record.t, direction, &unit_axis(self.axis), area)
+    }
+
+    fn sample_direction(&self, origin: &Vec3D, rng: &mut dyn RngCore) -> Option<Vec3D> {
+        let (a, b) = other_axes(self.axis);
+        let mut point = [0.0; 3];
+        point[self.axis] = self.k;
+        point[a] = self.min.0 + rng.gen::<f32>() * (self.max.0 - self.min.0);
+        point[b] = self.min.1 + rng.gen::<f32>() * (self.max.1 - self.min.1);
+        Some(Vec3D::new(point[0], point[1], point[2]) - *origin)
+    }
+}
+
+///
+/// Distance along the ray to the plane through point with the given normal,
+/// None for rays parallel to the plane
+///
+fn plane_hit(point: &Vec3D, normal: &Vec3D, ray: &Ray) -> Option<f32> {
+    let denominator = Vec3D::dot(normal, &ray.direction());
+    if denominator == 0.0 {
+        return None;
+    }
+    Some(Vec3D::dot(normal, &(*point - ray.origin())) / denominator)
+}
+
+///
+/// Two unit vectors perpendicular to the (unit) normal and to each other, used for surface coordinates.
+/// On walls the tangent is horizontal and the bitangent points up, on floors (normal close to y)
+/// the tangent is x. Use Vec3D::orthonormal_basis instead when the orientation doesn't matter,
+/// this one only exists so that textures keep a predictable orientation.
+///
+fn plane_basis(normal: &Vec3D) -> (Vec3D, Vec3D) {
+    let tangent = if normal.y().abs() < 0.999 {
+        Vec3D::cross(&Vec3D::new(0.0, 1.0, 0.0), normal)
+    } else {
+        Vec3D::cross(normal, &Vec3D::new(0.0, 0.0, 1.0))
+    }
+    .unit_vector();
+    let bitangent = Vec3D::cross(normal, &tangent);
+    (tangent, bitangent)
+}
+
+/// Unit vector along the given axis (0 = x, 1 = y, 2 = z)
+fn unit_axis(axis: usize) -> Vec3D {
+    match axis {
+        0 => Vec3D::new(1.0, 0.0, 0.0),
+        1 => Vec3D::new(0.0, 1.0, 0.0),
+        _ => Vec3D::new(0.0, 0.0, 1.0),
+    }
+}
+
+///
+/// Axis aligned box structure (named Cuboid to not clash with std's Box)
+///     min corner
+///     max corner
+/// (u, v) go from the min to the max corner of each face, along the two other axes in order.
+///
+pub struct Cuboid {
+    min: Vec3D,
+    max: Vec3D,
+    material: Arc<dyn Material>,
+}
+
+impl Cuboid {
+    /// Box between two opposite corners
+    pub fn new(a: Vec3D, b: Vec3D, material: Arc<dyn Material>) -> Self {
+        Self {
+            min: Vec3D::min(&a, &b),
+            max: Vec3D::max(&a, &b),
+            material,
+        }
+    }
+
+    pub fn min(&self) -> &Vec3D {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Vec3D {
+        &self.max
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        // slab method keeping track of the axis of the faces where the ray enters and leaves
+        let mut near = (f32::NEG_INFINITY, 0);
+        let mut far = (f32::INFINITY, 0);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction().axis(axis);
+            let mut t0 = (self.min.axis(axis) - ray.origin().axis(axis)) * inverse;
+            let mut t1 = (self.max.axis(axis) - ray.origin().axis(axis)) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > near.0 {
+                near = (t0, axis);
+            }
+            if t1 < far.0 {
+                far = (t1, axis);
+            }
+        }
+        if near.0 > far.0 {
+            return false;
+        }
+        // rays starting inside the box hit it where they leave
+        let (t, axis) = if near.0 < max && near.0 > min {
+            near
+        } else if far.0 < max && far.0 > min {
+            far
+        } else {
+            return false;
+        };
+
+        let point = ray.point_at(t);
+        let center = (self.min + self.max) * 0.5;
+        let normal = if point.axis(axis) > center.axis(axis) {
+            unit_axis(axis)
+        } else {
+            -unit_axis(axis)
+        };
+        let (a, b) = other_axes(axis);
+        let size = self.max - self.min;
+        record.t = t;
+        record.point = point;
+        record.normal = normal;
+        // flat boxes have no extent along some axis, keep their coordinates at 0 there
+        let fraction = |axis: usize| {
+            if size.axis(axis) > 0.0 {
+                (point.axis(axis) - self.min.axis(axis)) / size.axis(axis)
+            } else {
+                0.0
+            }
+        };
+        record.u = fraction(a);
+        record.v = fraction(b);
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // pad flat boxes so rays can hit them
+        let padding = Vec3D::new(1e-4, 1e-4, 1e-4);
+        Some(Aabb::new(self.min - padding, self.max + padding))
+    }
+}
+
+///
+/// Capped cylinder structure, standing along the y axis
+///     base center of the bottom cap
+///     radius
+///     height
+/// On the side u goes around the y axis and v from the bottom (0) to the top (1),
+/// on the caps u goes around the center and v from the center (0) to the edge (1).
+///
+pub struct Cylinder {
+    base: Vec3D,
+    radius: f32,
+    height: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3D, radius: f32, height: f32, material: Arc<dyn Material>) -> Self {
+        assert!(height != 0.0, "Invalid cylinder height {}", height);
+        Self {
+            base,
+            radius: radius.abs(),
+            height: height.abs(),
+            material,
+        }
+    }
+
+    pub fn base(&self) -> &Vec3D {
+        &self.base
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let origin = ray.origin() - self.base;
+        let direction = ray.direction();
+        let mut closest: Option<SurfaceHit> = None;
+
+        // side: x^2 + z^2 = radius^2 for 0 <= y <= height
+        let a = direction.x() * direction.x() + direction.z() * direction.z();
+        let b = 2.0 * (origin.x() * direction.x() + origin.z() * direction.z());
+        let c = origin.x() * origin.x() + origin.z() * origin.z() - self.radius * self.radius;
+        for t in solve_quadratic(a, b, c) {
+            let point = origin + t * direction;
+            if t < max && t > min && point.y() >= 0.0 && point.y() <= self.height {
+                let normal = Vec3D::new(point.x(), 0.0, point.z()) / self.radius;
+                let u = around_y(&point);
+                closest = closer(closest, (t, normal, u, point.y() / self.height));
+                break;
+            }
+        }
+
+        for (height, normal) in [(0.0, -1.0), (self.height, 1.0)] {
+            if let Some(hit) = cap_hit(&origin, &direction, height, normal, self.radius) {
+                if hit.0 < max && hit.0 > min {
+                    closest = closer(closest, hit);
+                }
+            }
+        }
+
+        match closest {
+            Some(hit) => {
+                record_hit(ray, hit, &self.material, record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3D::new(self.radius, 0.0, self.radius);
+        let top = Vec3D::new(self.radius, self.height, self.radius);
+        Some(Aabb::new(self.base - radius, self.base + top))
+    }
+}
+
+///
+/// Cone structure, standing along the y axis with its base capped
+///     base center of the bottom cap
+///     radius of the base
+///     height of the tip above the base
+/// On the side u goes around the y axis and v from the base (0) to the tip (1),
+/// on the cap u goes around the center and v from the center (0) to the edge (1).
+///
+pub struct Cone {
+    base: Vec3D,
+    radius: f32,
+    height: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(base: Vec3D, radius: f32, height: f32, material: Arc<dyn Material>) -> Self {
+        assert!(height != 0.0, "Invalid cone height {}", height);
+        Self {
+            base,
+            radius: radius.abs(),
+            height: height.abs(),
+            material,
+        }
+    }
+
+    pub fn base(&self) -> &Vec3D {
+        &self.base
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+}
+
+impl Hitable for Cone {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        let origin = ray.origin() - self.base;
+        let direction = ray.direction();
+        let mut closest: Option<SurfaceHit> = None;
+
+        // side: x^2 + z^2 = (k * (height - y))^2 for 0 <= y <= height, k = radius / height
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let q = self.height - origin.y();
+        let a = direction.x() * direction.x() + direction.z() * direction.z()
+            - k2 * direction.y() * direction.y();
+        let b = 2.0 * (origin.x() * direction.x() + origin.z() * direction.z())
+            + 2.0 * k2 * q * direction.y();
+        let c = origin.x() * origin.x() + origin.z() * origin.z() - k2 * q * q;
+        for t in solve_quadratic(a, b, c) {
+            let point = origin + t * direction;
+            if t < max && t > min && point.y() >= 0.0 && point.y() <= self.height {
+                // gradient of x^2 + z^2 - (k * (height - y))^2
+                let normal = Vec3D::new(point.x(), k2 * (self.height - point.y()), point.z());
+                let normal = if normal.squared_length() > 0.0 {
+                    normal.unit_vector()
+                } else {
+                    Vec3D::new(0.0, 1.0, 0.0)
+                };
+                let u = around_y(&point);
+                closest = closer(closest, (t, normal, u, point.y() / self.height));
+                break;
+            }
+        }
+
+        if let Some(hit) = cap_hit(&origin, &direction, 0.0, -1.0, self.radius) {
+            if hit.0 < max && hit.0 > min {
+                closest = closer(closest, hit);
+            }
+        }
+
+        match closest {
+            Some(hit) => {
+                record_hit(ray, hit, &self.material, record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3D::new(self.radius, 0.0, self.radius);
+        let top = Vec3D::new(self.radius, self.height, self.radius);
+        Some(Aabb::new(self.base - radius, self.base + top))
+    }
+}
+
+///
+/// Torus structure, lying on the xz plane around the y axis
+///     center
+///     major radius from the center to the middle of the tube
+///     minor radius of the tube
+/// u goes around the y axis, v around the tube starting at the outer equator.
+///
+pub struct Torus {
+    center: Vec3D,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3D,
+        major_radius: f32,
+        minor_radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            center,
+            major_radius: major_radius.abs(),
+            minor_radius: minor_radius.abs(),
+            material,
+        }
+    }
+
+    pub fn center(&self) -> &Vec3D {
+        &self.center
+    }
+
+    pub fn major_radius(&self) -> f32 {
+        self.major_radius
+    }
+
+    pub fn minor_radius(&self) -> f32 {
+        self.minor_radius
+    }
+}
+
+impl Hitable for Torus {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        // The quartic is solved in double precision with a unit direction, starting from where
+        // the ray enters the bounding sphere so that far away rays don't lose precision.
+        let length = ray.direction().length() as f64;
+        if length == 0.0 {
+            return false;
+        }
+        let to_f64 = |v: Vec3D| [v.x() as f64, v.y() as f64, v.z() as f64];
+        let direction = to_f64(ray.direction()).map(|component| component / length);
+        let mut origin = to_f64(ray.origin() - self.center);
+        let dot = |a: &[f64; 3], b: &[f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+        let big = self.major_radius as f64;
+        let small = self.minor_radius as f64;
+
+        let bound = big + small;
+        let half_b = dot(&origin, &direction);
+        let discriminant = half_b * half_b - (dot(&origin, &origin) - bound * bound);
+        if discriminant <= 0.0 {
+            return false;
+        }
+        let start = (-half_b - discriminant.sqrt()).max(0.0);
+        for (o, d) in origin.iter_mut().zip(&direction) {
+            *o += start * d;
+        }
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) with p = origin + s * direction
+        let n = dot(&origin, &direction);
+        let k = dot(&origin, &origin) + big * big - small * small;
+        let four_r2 = 4.0 * big * big;
+        let horizontal = direction[0] * direction[0] + direction[2] * direction[2];
+        let mixed = origin[0] * direction[0] + origin[2] * direction[2];
+        let flat = origin[0] * origin[0] + origin[2] * origin[2];
+        let coefficients = [
+            4.0 * n,
+            4.0 * n * n + 2.0 * k - four_r2 * horizontal,
+            4.0 * n * k - 2.0 * four_r2 * mixed,
+            k * k - four_r2 * flat,
+        ];
+        let mut closest = f64::INFINITY;
+        for s in solve_quartic(coefficients) {
+            let t = (start + s) / length;
+            if t < max as f64 && t > min as f64 && t < closest {
+                closest = t;
+            }
+        }
+        if closest == f64::INFINITY {
+            return false;
+        }
+        let t = closest as f32;
+
+        let point = ray.point_at(t);
+        let local = point - self.center;
+        // gradient of (|p|^2 + R^2 - r^2)^2 - 4 R^2 (x^2 + z^2), divided by 4
+        let sum = local.squared_length()
+            - self.major_radius * self.major_radius
+            - self.minor_radius * self.minor_radius;
+        let normal = Vec3D::new(
+            local.x() * sum,
+            local.y() * (sum + 2.0 * self.major_radius * self.major_radius),
+            local.z() * sum,
+        );
+        let distance = (local.x() * local.x() + local.z() * local.z()).sqrt();
+        let tube_angle = local.y().atan2(distance - self.major_radius);
+        record.t = t;
+        record.point = point;
+        record.normal = if normal.squared_length() > 0.0 {
+            normal.unit_vector()
+        } else {
+            Vec3D::new(0.0, 1.0, 0.0)
+        };
+        record.u = around_y(&local);
+        record.v = tube_angle.rem_euclid(2.0 * std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+        record.material = self.material.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vec3D::new(outer, self.minor_radius, outer);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+/// Hit found on a surface: t, normal, u, v
+type SurfaceHit = (f32, Vec3D, f32, f32);
+
+/// Keep the closest of two hits
+fn closer(closest: Option<SurfaceHit>, hit: SurfaceHit) -> Option<SurfaceHit> {
+    match closest {
+        Some(closest) if closest.0 <= hit.0 => Some(closest),
+        _ => Some(hit),
+    }
+}
+
+///
+/// Hit on the cap (a disk around the y axis) at the given height, facing up (1) or down (-1).
+/// The origin is relative to the center of the base.
+///
+fn cap_hit(
+    origin: &Vec3D,
+    direction: &Vec3D,
+    height: f32,
+    facing: f32,
+    radius: f32,
+) -> Option<SurfaceHit> {
+    if direction.y() == 0.0 {
+        return None;
+    }
+    let t = (height - origin.y()) / direction.y();
+    let point = *origin + t * direction;
+    let distance = (point.x() * point.x() + point.z() * point.z()).sqrt();
+    if distance > radius {
+        return None;
+    }
+    let normal = Vec3D::new(0.0, facing, 0.0);
+    Some((t, normal, around_y(&point), distance / radius))
+}
+
+/// Angle around the y axis in [0, 1], the same way as for spheres
+fn around_y(point: &Vec3D) -> f32 {
+    let phi = (-point.z()).atan2(point.x()) + std::f32::consts::PI;
+    phi / (2.0 * std::f32::consts::PI)
+}
+
+/// Write a surface hit to the record
+fn record_hit(ray: &Ray, hit: SurfaceHit, material: &Arc<dyn Material>, record: &mut HitRecord) {
+    let (t, normal, u, v) = hit;
+    record.t = t;
+    record.point = ray.point_at(t);
+    record.normal = normal;
+    record.u = u;
+    record.v = v;
+    record.material = material.clone();
+}
+
+/// The two axes other than the given one, in order
+fn other_axes(axis: usize) -> (usize, usize) {
+    match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    }
+}
+
+///
+/// Real roots of a x^2 + b x + c, in increasing order
+///
+fn solve_quadratic(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    // avoid cancellation between b and the square root
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    if t0 < t1 {
+        vec![t0, t1]
+    } else {
+        vec![t1, t0]
+    }
+}
+
+///
+/// Real roots of x^3 + a x^2 + b x + c (unordered)
+/// From Jochen Schwarze, Cubic and Quartic Roots, Graphics Gems (1990).
+///
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    const EPSILON: f64 = 1e-12;
+    // substitute x = y - a/3 to get y^3 + 3 p y + 2 q = 0
+    let p = (b - a * a / 3.0) / 3.0;
+    let q = (2.0 / 27.0 * a * a * a - a * b / 3.0 + c) / 2.0;
+    let cube_p = p * p * p;
+    let discriminant = q * q + cube_p;
+    let mut roots = if discriminant.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // three real roots
+        let phi = (-q / (-cube_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        let third = std::f64::consts::PI / 3.0;
+        vec![
+            t * phi.cos(),
+            -t * (phi + third).cos(),
+            -t * (phi - third).cos(),
+        ]
+    } else {
+        let root = discriminant.sqrt();
+        vec![(root - q).cbrt() - (root + q).cbrt()]
+    };
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+///
+/// Real roots of x^4 + a x^3 + b x^2 + c x + d given as [a, b, c, d] (unordered)
+/// From Jochen Schwarze, Cubic and Quartic Roots, Graphics Gems (1990),
+/// the roots are refined with Newton's method.
+///
+fn solve_quartic([a, b, c, d]: [f64; 4]) -> Vec<f64> {
+    const EPSILON: f64 = 1e-12;
+    // substitute x = y - a/4 to get y^4 + p y^2 + q y + r = 0
+    let a2 = a * a;
+    let p = -3.0 / 8.0 * a2 + b;
+    let q = a2 * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * a2 * a2 + a2 * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = Vec::new();
+    if r.abs() < EPSILON {
+        // y (y^3 + p y + q) = 0
+        roots.push(0.0);
+        roots.extend(solve_cubic(0.0, p, q));
+    } else {
+        // solve the resolvent cubic and use one of its roots to split in two quadratics
+        let z = solve_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let root = |value: f64| {
+            if value.abs() < EPSILON {
+                Some(0.0)
+            } else if value > 0.0 {
+                Some(value.sqrt())
+            } else {
+                None
+            }
+        };
+        let (u, v) = match (root(u), root(v)) {
+            (Some(u), Some(v)) => (u, v),
+            _ => return roots,
+        };
+        let v = if q < 0.0 { -v } else { v };
+        for (b, c) in [(v, z - u), (-v, z + u)] {
+            let discriminant = b * b / 4.0 - c;
+            if discriminant.abs() < EPSILON {
+                roots.push(-b / 2.0);
+            } else if discriminant > 0.0 {
+                let root = discriminant.sqrt();
+                roots.push(-b / 2.0 + root);
+                roots.push(-b / 2.0 - root);
+            }
+        }
+    }
+
+    let polynomial = |x: f64| (((x + a) * x + b) * x + c) * x + d;
+    let derivative = |x: f64| ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let slope = derivative(*root);
+            if slope != 0.0 {
+                *root -= polynomial(*root) / slope;
+            }
+        }
+    }
+    roots
 }
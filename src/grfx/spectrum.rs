@@ -0,0 +1,140 @@
+use crate::grfx::color::Rgb;
+use crate::grfx::transform::Mat4;
+use crate::grfx::vector::Vec3D;
+use std::sync::OnceLock;
+
+//
+// Conversions between RGB colors and spectra for spectral rendering, where every path carries
+// a single wavelength (in nanometers) instead of the three RGB channels.
+//
+// Colors (albedos, lights, backgrounds) are upsampled to smooth spectra going back to the same
+// color, and the radiance found for a wavelength is turned back into RGB through the CIE XYZ
+// color matching functions. Both are white balanced: the spectrum of white is 1 everywhere and
+// a constant spectrum of 1 averages out to white.
+//
+
+/// Shortest wavelength (nm) traced, light outside the visible range doesn't change the image
+pub const MIN_WAVELENGTH: f32 = 380.0;
+
+/// Longest wavelength (nm) traced
+pub const MAX_WAVELENGTH: f32 = 720.0;
+
+/// Wavelength in [MIN_WAVELENGTH, MAX_WAVELENGTH] for a number u in [0, 1), uniformly distributed
+pub fn sample_wavelength(u: f32) -> f32 {
+    MIN_WAVELENGTH + u * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+///
+/// CIE 1931 color matching functions (x̄, ȳ, z̄) at the given wavelength, using the piecewise
+/// gaussian fit of Wyman, Sloan and Shirley (2013)
+///
+pub fn cie_xyz(wavelength: f32) -> Vec3D {
+    let gaussian = |mean: f32, below: f32, above: f32| {
+        let sigma = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3D::new(
+        1.056 * gaussian(599.8, 37.9, 31.0) + 0.362 * gaussian(442.0, 16.0, 26.7)
+            - 0.065 * gaussian(501.1, 20.4, 26.2),
+        0.821 * gaussian(568.8, 46.9, 40.5) + 0.286 * gaussian(530.9, 16.3, 31.1),
+        1.217 * gaussian(437.0, 11.8, 36.0) + 0.681 * gaussian(459.0, 26.0, 13.8),
+    )
+}
+
+/// Linear sRGB color from CIE XYZ coordinates
+pub fn xyz_to_rgb(xyz: &Vec3D) -> Rgb {
+    Rgb::new(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
+
+///
+/// Color contributed by the radiance found for a wavelength drawn with sample_wavelength.
+/// Averaging it over the wavelengths gives the color of the spectrum.
+///
+pub fn to_rgb(wavelength: f32, radiance: f32) -> Rgb {
+    rgb_response(wavelength) * radiance
+}
+
+///
+/// Value of the spectrum upsampled from the color at the given wavelength: a blend of smooth
+/// blue, green and red bands weighted so that the spectrum goes back to the same color.
+/// Saturated colors may need negative weights, the spectrum is kept positive.
+///
+pub fn from_rgb(color: &Rgb, wavelength: f32) -> f32 {
+    let weights =
+        tables()
+            .to_weights
+            .transform_vector(&Vec3D::new(color.r(), color.g(), color.b()));
+    let bands = bands(wavelength);
+    Vec3D::dot(&weights, &bands).max(0.0)
+}
+
+/// Smooth blue, green and red bands adding up to 1 at every wavelength
+fn bands(wavelength: f32) -> Vec3D {
+    let step = |from: f32, to: f32| {
+        let t = ((wavelength - from) / (to - from)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let blue = 1.0 - step(480.0, 510.0);
+    let red = step(570.0, 600.0);
+    Vec3D::new(red, 1.0 - red - blue, blue)
+}
+
+/// White balanced RGB color matching functions, averaging to 1 over the traced wavelengths
+fn rgb_response(wavelength: f32) -> Rgb {
+    let white = tables().white;
+    let rgb = xyz_to_rgb(&cie_xyz(wavelength));
+    Rgb::new(
+        rgb.r() / white.r(),
+        rgb.g() / white.g(),
+        rgb.b() / white.b(),
+    )
+}
+
+struct Tables {
+    /// Color of the constant spectrum of 1, before white balancing
+    white: Rgb,
+    /// Weights of the bands giving a color (inverse of the colors of the bands)
+    to_weights: Mat4,
+}
+
+/// Tables computed on first use by averaging over the traced wavelengths (1 nm steps)
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let steps = (MAX_WAVELENGTH - MIN_WAVELENGTH) as usize;
+        let wavelengths = || (0..steps).map(|i| MIN_WAVELENGTH + i as f32 + 0.5);
+        let mut white = Rgb::BLACK;
+        for wavelength in wavelengths() {
+            white += xyz_to_rgb(&cie_xyz(wavelength)) / steps as f32;
+        }
+        // balanced colors of the bands, one per column
+        let mut colors = [[0.0; 4]; 4];
+        colors[3][3] = 1.0;
+        for wavelength in wavelengths() {
+            let rgb = xyz_to_rgb(&cie_xyz(wavelength));
+            let rgb = [
+                rgb.r() / white.r(),
+                rgb.g() / white.g(),
+                rgb.b() / white.b(),
+            ];
+            let bands = bands(wavelength);
+            let bands = [bands.x(), bands.y(), bands.z()];
+            for (row, channel) in rgb.iter().enumerate() {
+                for (column, band) in bands.iter().enumerate() {
+                    colors[row][column] += channel * band / steps as f32;
+                }
+            }
+        }
+        Tables {
+            white,
+            to_weights: Mat4::new(colors)
+                .inverse()
+                .expect("the bands have independent colors"),
+        }
+    })
+}
@@ -0,0 +1,220 @@
+use crate::grfx::color::Rgb;
+use crate::grfx::image::Image;
+use crate::grfx::perlin::Perlin;
+use crate::grfx::vector::Vec3D;
+use std::io;
+use std::sync::Arc;
+
+///
+/// Texture trait
+/// Colors that vary over the surface of a shape, used as albedo by materials.
+///
+/// to implement this trait define the value method:
+///     fn value(&self, u : f32, v : f32, point : &Vec3D) -> Rgb;
+///     (u, v) -> surface coordinates of the point hit (see HitRecord)
+///     point -> point hit, for solid textures
+///
+/// Textures are shared between render threads, hence Send + Sync.
+///
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, point: &Vec3D) -> Rgb;
+}
+
+/// Same color everywhere
+#[derive(Debug, Clone)]
+pub struct SolidColor {
+    color: Rgb,
+}
+
+impl SolidColor {
+    pub fn new(color: Rgb) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _point: &Vec3D) -> Rgb {
+        self.color
+    }
+}
+
+///
+/// 3D checkerboard: space is split in cubes of side scale alternating between
+/// the even and odd textures, so any shape can be checkered without caring about its uvs.
+///
+pub struct Checker {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    scale: f32,
+}
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f32) -> Self {
+        Self { even, odd, scale }
+    }
+
+    /// Checkerboard alternating between two colors
+    pub fn colors(even: Rgb, odd: Rgb, scale: f32) -> Self {
+        Self::new(
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+            scale,
+        )
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, point: &Vec3D) -> Rgb {
+        // surfaces lying on a cube face (like the y = 0 plane) would flicker between both
+        // textures due to rounding, nudge points right on the border into the upper cube
+        let cell = |coordinate: f32| (coordinate / self.scale + 1e-4).floor() as i64;
+        if (cell(point.x()) + cell(point.y()) + cell(point.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+///
+/// Texture mapping an image over the (u, v) coordinates,
+/// (0, 0) is the bottom left corner of the image and (1, 1) the top right one.
+/// Pixels are gamma decoded (2.0 unless changed with with_gamma) into linear colors.
+///
+pub struct ImageTexture {
+    image: Image,
+    gamma: f32,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        Self { image, gamma: 2.0 }
+    }
+
+    /// Read the image from a PNG or PPM file, see Image::read_image
+    pub fn load(filename: &str) -> Result<Self, io::Error> {
+        Ok(Self::new(Image::read_image(filename)?))
+    }
+
+    /// Gamma used to encode the image
+    pub fn with_gamma(mut self, gamma: f32) -> Self {
+        self.gamma = gamma;
+        self
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _point: &Vec3D) -> Rgb {
+        let (width, height) = (self.image.width(), self.image.height());
+        if width == 0 || height == 0 {
+            return Rgb::new(1.0, 0.0, 1.0);
+        }
+        let x = (u.clamp(0.0, 1.0) * width as f32) as u32;
+        let y = ((1.0 - v.clamp(0.0, 1.0)) * height as f32) as u32;
+        let color = Rgb::from(self.image.get(x.min(width - 1), y.min(height - 1)));
+        let decode = |channel: f32| channel.powf(self.gamma);
+        Rgb::new(decode(color.r()), decode(color.g()), decode(color.b()))
+    }
+}
+
+/// Looks made from Perlin noise, see NoiseTexture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoisePattern {
+    /// Plain noise, soft blobs
+    Smooth,
+    /// Turbulence, creased like smoke
+    Turbulence,
+    /// Fractal noise, rolling like terrain or clouds
+    Fractal,
+    /// Stripes across x bent by turbulence
+    Marble,
+    /// Rings around the y axis disturbed by noise
+    Wood,
+}
+
+impl NoisePattern {
+    /// Pattern from its lowercase name (as used in scene files)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "smooth" => Some(NoisePattern::Smooth),
+            "turbulence" => Some(NoisePattern::Turbulence),
+            "fractal" => Some(NoisePattern::Fractal),
+            "marble" => Some(NoisePattern::Marble),
+            "wood" => Some(NoisePattern::Wood),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoisePattern::Smooth => "smooth",
+            NoisePattern::Turbulence => "turbulence",
+            NoisePattern::Fractal => "fractal",
+            NoisePattern::Marble => "marble",
+            NoisePattern::Wood => "wood",
+        }
+    }
+}
+
+///
+/// Procedural texture from seeded Perlin noise.
+///     pattern -> look of the texture, see NoisePattern
+///     scale -> frequency of the noise, larger values give smaller features
+///     octaves -> number of noise layers added by turbulence, fractal, marble and wood (7 by default)
+///     low, high -> colors the noise goes between (black and white by default)
+///
+pub struct NoiseTexture {
+    perlin: Perlin,
+    pattern: NoisePattern,
+    scale: f32,
+    octaves: u32,
+    low: Rgb,
+    high: Rgb,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, pattern: NoisePattern, scale: f32) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            pattern,
+            scale,
+            octaves: 7,
+            low: Rgb::BLACK,
+            high: Rgb::WHITE,
+        }
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_colors(mut self, low: Rgb, high: Rgb) -> Self {
+        self.low = low;
+        self.high = high;
+        self
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, point: &Vec3D) -> Rgb {
+        let scaled = *point * self.scale;
+        let amount = match self.pattern {
+            NoisePattern::Smooth => 0.5 * (1.0 + self.perlin.noise(&scaled)),
+            NoisePattern::Turbulence => self.perlin.turbulence(&scaled, self.octaves),
+            NoisePattern::Fractal => 0.5 * (1.0 + self.perlin.fractal(&scaled, self.octaves)),
+            NoisePattern::Marble => {
+                let phase = scaled.x() + 10.0 * self.perlin.turbulence(point, self.octaves);
+                0.5 * (1.0 + phase.sin())
+            }
+            NoisePattern::Wood => {
+                let distance = (scaled.x() * scaled.x() + scaled.z() * scaled.z()).sqrt();
+                // one ring per unit of scaled distance
+                let rings = distance + 0.2 * self.perlin.fractal(&scaled, self.octaves);
+                rings.rem_euclid(1.0)
+            }
+        };
+        let amount = amount.clamp(0.0, 1.0);
+        self.low * (1.0 - amount) + self.high * amount
+    }
+}
@@ -0,0 +1,393 @@
+use crate::grfx::aabb::Aabb;
+use crate::grfx::ray::Ray;
+use crate::grfx::shape::{HitRecord, Hitable};
+use crate::grfx::vector::Vec3D;
+use rand::RngCore;
+use std::ops::Mul;
+use std::sync::Arc;
+
+///
+/// 4x4 matrix (row major) for affine transformations of points and vectors.
+/// Points are column vectors: the transformed point is matrix * (x, y, z, 1).
+/// Multiplying a * b gives the transformation applying b first and then a.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4([[f32; 4]; 4]);
+
+impl Mat4 {
+    /// Create a matrix from its rows
+    pub fn new(rows: [[f32; 4]; 4]) -> Self {
+        Self(rows)
+    }
+
+    pub fn identity() -> Self {
+        Self::scaling(Vec3D::new(1.0, 1.0, 1.0))
+    }
+
+    /// Move points by offset
+    pub fn translation(offset: Vec3D) -> Self {
+        Self([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Scale each axis by the corresponding factor
+    pub fn scaling(factors: Vec3D) -> Self {
+        Self([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Rotate around the given axis (through the origin) by an angle in degrees,
+    /// counter clockwise when the axis points towards the viewer
+    ///
+    pub fn rotation(axis: Vec3D, degrees: f32) -> Self {
+        let axis = axis.unit_vector();
+        let (x, y, z) = (axis.x(), axis.y(), axis.z());
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Retrieve the element at row, column
+    pub fn get(&self, row: usize, column: usize) -> f32 {
+        self.0[row][column]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = [[0.0; 4]; 4];
+        for (row, values) in result.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = self.0[column][row];
+            }
+        }
+        Self(result)
+    }
+
+    ///
+    /// Inverse matrix computed with Gauss-Jordan elimination,
+    /// None when the matrix is singular (i.e. scaling by 0)
+    ///
+    pub fn inverse(&self) -> Option<Self> {
+        let mut left = self.0;
+        let mut right = Self::identity().0;
+        for column in 0..4 {
+            // partial pivoting: use the row with the largest value in this column
+            let pivot = (column..4)
+                .max_by(|a, b| left[*a][column].abs().total_cmp(&left[*b][column].abs()))?;
+            if left[pivot][column].abs() <= f32::EPSILON * 1e-3 {
+                return None;
+            }
+            left.swap(column, pivot);
+            right.swap(column, pivot);
+            let divisor = left[column][column];
+            for i in 0..4 {
+                left[column][i] /= divisor;
+                right[column][i] /= divisor;
+            }
+            for row in 0..4 {
+                let factor = left[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for i in 0..4 {
+                    left[row][i] -= factor * left[column][i];
+                    right[row][i] -= factor * right[column][i];
+                }
+            }
+        }
+        Some(Self(right))
+    }
+
+    /// Transform a point (affected by translations)
+    pub fn transform_point(&self, point: &Vec3D) -> Vec3D {
+        let m = &self.0;
+        let (x, y, z) = (point.x(), point.y(), point.z());
+        let w = m[3][0] * x + m[3][1] * y + m[3][2] * z + m[3][3];
+        let result = Vec3D::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z + m[0][3],
+            m[1][0] * x + m[1][1] * y + m[1][2] * z + m[1][3],
+            m[2][0] * x + m[2][1] * y + m[2][2] * z + m[2][3],
+        );
+        if w == 1.0 || w == 0.0 {
+            result
+        } else {
+            result / w
+        }
+    }
+
+    /// Transform a direction (not affected by translations)
+    pub fn transform_vector(&self, vector: &Vec3D) -> Vec3D {
+        let m = &self.0;
+        let (x, y, z) = (vector.x(), vector.y(), vector.z());
+        Vec3D::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z,
+            m[1][0] * x + m[1][1] * y + m[1][2] * z,
+            m[2][0] * x + m[2][1] * y + m[2][2] * z,
+        )
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Operator * (matrix product)
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Self::Output {
+        let mut result = [[0.0; 4]; 4];
+        for (row, values) in result.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|i| self.0[row][i] * other.0[i][column]).sum();
+            }
+        }
+        Mat4(result)
+    }
+}
+
+///
+/// Transformation matrix along with its inverse.
+///
+/// Transforms are built by chaining operations, applied in the order written:
+///     Transform::identity().scale(Vec3D::new(2.0, 2.0, 2.0)).rotate_y(45.0).translate(offset)
+/// scales first, then rotates and finally translates.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    /// Transform from a matrix, None if it can't be inverted
+    pub fn from_matrix(matrix: Mat4) -> Option<Self> {
+        Some(Self {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Mat4 {
+        &self.inverse
+    }
+
+    /// Apply other after this transform
+    pub fn then(&self, other: &Transform) -> Self {
+        Self {
+            matrix: other.matrix * self.matrix,
+            inverse: self.inverse * other.inverse,
+        }
+    }
+
+    pub fn translate(&self, offset: Vec3D) -> Self {
+        self.then(&Self {
+            matrix: Mat4::translation(offset),
+            inverse: Mat4::translation(-offset),
+        })
+    }
+
+    /// Scale by the given factors, panics if any of them is 0
+    pub fn scale(&self, factors: Vec3D) -> Self {
+        assert!(
+            factors.x() != 0.0 && factors.y() != 0.0 && factors.z() != 0.0,
+            "Can't scale by 0"
+        );
+        let inverse = Vec3D::new(1.0 / factors.x(), 1.0 / factors.y(), 1.0 / factors.z());
+        self.then(&Self {
+            matrix: Mat4::scaling(factors),
+            inverse: Mat4::scaling(inverse),
+        })
+    }
+
+    /// Rotate around an axis through the origin, see Mat4::rotation
+    pub fn rotate(&self, axis: Vec3D, degrees: f32) -> Self {
+        let matrix = Mat4::rotation(axis, degrees);
+        // rotation matrices are orthogonal
+        self.then(&Self {
+            matrix,
+            inverse: matrix.transpose(),
+        })
+    }
+
+    pub fn rotate_x(&self, degrees: f32) -> Self {
+        self.rotate(Vec3D::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(&self, degrees: f32) -> Self {
+        self.rotate(Vec3D::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(&self, degrees: f32) -> Self {
+        self.rotate(Vec3D::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    pub fn point(&self, point: &Vec3D) -> Vec3D {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn vector(&self, vector: &Vec3D) -> Vec3D {
+        self.matrix.transform_vector(vector)
+    }
+
+    ///
+    /// Transform a surface normal, normals are transformed by the inverse transpose
+    /// to stay perpendicular to the surface. The result isn't normalized.
+    ///
+    pub fn normal(&self, normal: &Vec3D) -> Vec3D {
+        self.inverse.transpose().transform_vector(normal)
+    }
+
+    /// Box containing the transformed box (bounding the 8 transformed corners)
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        let (min, max) = (bbox.min(), bbox.max());
+        let mut result = Aabb::empty();
+        for corner in 0..8 {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    min.axis(axis)
+                } else {
+                    max.axis(axis)
+                }
+            };
+            result = result.grow(self.point(&Vec3D::new(pick(0), pick(1), pick(2))));
+        }
+        result
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+///
+/// Object placed in the world with a transform.
+///
+/// Rays are moved into the object space, hit there and the results brought back to the world,
+/// so the same object (i.e. a mesh) can be shared by many instances with different placements.
+///
+pub struct Instance {
+    object: Arc<dyn Hitable>,
+    transform: Transform,
+    normal_matrix: Mat4,
+    bbox: Option<Aabb>,
+    /// Volume scaling of the transform, |det| of its linear part
+    scaling: f32,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hitable>, transform: Transform) -> Self {
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| transform.bounding_box(&bbox));
+        let m = |row: usize, column: usize| transform.matrix().get(row, column);
+        let determinant = m(0, 0) * (m(1, 1) * m(2, 2) - m(1, 2) * m(2, 1))
+            - m(0, 1) * (m(1, 0) * m(2, 2) - m(1, 2) * m(2, 0))
+            + m(0, 2) * (m(1, 0) * m(2, 1) - m(1, 1) * m(2, 0));
+        Self {
+            object,
+            normal_matrix: transform.inverse().transpose(),
+            transform,
+            bbox,
+            scaling: determinant.abs(),
+        }
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hitable> {
+        &self.object
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, ray: &Ray, min: f32, max: f32, record: &mut HitRecord) -> bool {
+        // the direction isn't normalized so t is the same in both spaces
+        let inverse = self.transform.inverse();
+        let local = Ray::with_time(
+            inverse.transform_point(&ray.origin()),
+            inverse.transform_vector(&ray.direction()),
+            ray.time(),
+        );
+        if !self.object.hit(&local, min, max, record) {
+            return false;
+        }
+        record.point = ray.point_at(record.t);
+        let normal = self.normal_matrix.transform_vector(&record.normal);
+        if normal.squared_length() > 0.0 {
+            record.normal = normal.unit_vector();
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    ///
+    /// Density of the object for the direction taken to object space, converted back to world
+    /// directions: the transform stretches solid angles by |det| / |inverse * direction|³
+    ///
+    fn pdf_value(&self, origin: &Vec3D, direction: &Vec3D) -> f32 {
+        let inverse = self.transform.inverse();
+        let local = inverse.transform_vector(&direction.unit_vector());
+        let length = local.length();
+        if length == 0.0 || self.scaling == 0.0 {
+            return 0.0;
+        }
+        let pdf = self
+            .object
+            .pdf_value(&inverse.transform_point(origin), &(local / length));
+        pdf / (self.scaling * length * length * length)
+    }
+
+    fn sample_direction(&self, origin: &Vec3D, rng: &mut dyn RngCore) -> Option<Vec3D> {
+        let local_origin = self.transform.inverse().transform_point(origin);
+        let local = self.object.sample_direction(&local_origin, rng)?;
+        Some(self.transform.vector(&local))
+    }
+}
@@ -61,6 +61,16 @@ impl Vec3D {
         self.2
     }
 
+    /// Retrieve component by axis index (0 = x, 1 = y, 2 = z)
+    pub fn axis(&self, index: usize) -> f32 {
+        match index {
+            0 => self.0,
+            1 => self.1,
+            2 => self.2,
+            _ => panic!("Invalid axis index {}", index),
+        }
+    }
+
     /// Calculate the squared length/magnitude of the Vec3D
     pub fn squared_length(&self) -> f32 {
         self.0.powi(2) + self.1.powi(2) + self.2.powi(2)
@@ -91,6 +101,24 @@ impl Vec3D {
     pub fn dot(left: &Self, right: &Self) -> f32 {
         left.0 * right.0 + left.1 * right.1 + left.2 * right.2
     }
+    /// Component wise minimum of two vectors
+    pub fn min(left: &Self, right: &Self) -> Self {
+        Self(
+            left.0.min(right.0),
+            left.1.min(right.1),
+            left.2.min(right.2),
+        )
+    }
+
+    /// Component wise maximum of two vectors
+    pub fn max(left: &Self, right: &Self) -> Self {
+        Self(
+            left.0.max(right.0),
+            left.1.max(right.1),
+            left.2.max(right.2),
+        )
+    }
+
     /// Calculate doct product
     /// ai + bj + ck
     /// wi + yj + zk
@@ -104,6 +132,40 @@ impl Vec3D {
             left.0 * right.1 - right.0 * left.1,
         )
     }
+
+    ///
+    /// Two unit vectors perpendicular to this (unit) vector and to each other, so that
+    /// (first, second, self) is a right handed orthonormal basis.
+    /// Uses the branchless construction of Duff et al. (2017), which has no degenerate direction.
+    ///
+    pub fn orthonormal_basis(&self) -> (Self, Self) {
+        let sign = 1.0f32.copysign(self.2);
+        let a = -1.0 / (sign + self.2);
+        let b = self.0 * self.1 * a;
+        (
+            Self(1.0 + sign * self.0 * self.0 * a, sign * b, -sign * self.0),
+            Self(b, sign + self.1 * self.1 * a, -self.1),
+        )
+    }
+
+    ///
+    /// Vector given by its coordinates in the orthonormal basis around this (unit) vector,
+    /// z being along this vector (i.e. a direction sampled around a surface normal)
+    ///
+    pub fn local_to_world(&self, local: &Self) -> Self {
+        let (tangent, bitangent) = self.orthonormal_basis();
+        local.0 * tangent + local.1 * bitangent + local.2 * *self
+    }
+
+    /// Coordinates of the vector in the orthonormal basis around this (unit) vector, see local_to_world
+    pub fn world_to_local(&self, vector: &Self) -> Self {
+        let (tangent, bitangent) = self.orthonormal_basis();
+        Self(
+            Self::dot(vector, &tangent),
+            Self::dot(vector, &bitangent),
+            Self::dot(vector, self),
+        )
+    }
 }
 /* Operator overloading */
 
@@ -185,7 +247,7 @@ impl MulAssign<f32> for Vec3D {
     fn mul_assign(&mut self, scalar: f32) {
         self.0 *= scalar;
         self.1 *= scalar;
-        self.1 *= scalar;
+        self.2 *= scalar;
     }
 }
 
@@ -195,7 +257,7 @@ impl DivAssign<f32> for Vec3D {
         assert_ne!(scalar, 0.0);
         self.0 /= scalar;
         self.1 /= scalar;
-        self.1 /= scalar;
+        self.2 /= scalar;
     }
 }
 
@@ -25,6 +25,15 @@ fn vector_multiply() {
     assert_eq!(lhs * rhs, Vec3D::new(2.0, 4.0, 6.0));
 }
 
+#[test]
+fn vector_multiply_divide_assign() {
+    let mut vector = Vec3D::new(1.0,2.0,3.0);
+    vector *= 2.0;
+    assert_eq!(vector, Vec3D::new(2.0, 4.0, 6.0));
+    vector /= 4.0;
+    assert_eq!(vector, Vec3D::new(0.5, 1.0, 1.5));
+}
+
 #[test]
 fn vector_divide() {
     let lhs = Vec3D::new(2.0,4.0,6.0);
@@ -37,3 +46,1470 @@ fn vector_unit() {
     let lhs = Vec3D::new(5.0,5.0,5.0);
     assert_eq!(lhs.unit_vector(), Vec3D::new(1.0/3.0_f32.sqrt(),1.0/3.0_f32.sqrt(),1.0/3.0_f32.sqrt()));
 }
+
+#[test]
+fn aabb_hit() {
+    use crate::grfx::aabb::Aabb;
+    use crate::grfx::ray::Ray;
+    let bbox = Aabb::new(Vec3D::new(-1.0, -1.0, -1.0), Vec3D::new(1.0, 1.0, 1.0));
+    let towards = Ray::new(Vec3D::new(0.0, 0.0, -5.0), Vec3D::new(0.0, 0.0, 1.0));
+    let away = Ray::new(Vec3D::new(0.0, 0.0, -5.0), Vec3D::new(0.0, 0.0, -1.0));
+    let beside = Ray::new(Vec3D::new(2.0, 0.0, -5.0), Vec3D::new(0.0, 0.0, 1.0));
+    assert!(bbox.hit(&towards, 0.0, f32::MAX));
+    assert!(!bbox.hit(&towards, 0.0, 3.0));
+    assert!(!bbox.hit(&away, 0.0, f32::MAX));
+    assert!(!bbox.hit(&beside, 0.0, f32::MAX));
+}
+
+#[test]
+fn bvh_matches_hit_list() {
+    use crate::grfx::bvh::BvhNode;
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::Lambertian;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitList, HitRecord, Hitable, Sphere};
+    use std::sync::Arc;
+
+    let build = || {
+        let mut list = HitList::new();
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = Vec3D::new(i as f32 - 5.0, (i * j % 7) as f32 * 0.3, j as f32 - 5.0);
+                let material = Arc::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)));
+                list.add(Arc::new(Sphere::new(center, 0.3 + (i + j) as f32 * 0.01, material)));
+            }
+        }
+        list
+    };
+    let list = build();
+    let bvh = BvhNode::new(build());
+    for k in 0..200 {
+        let angle = k as f32 * 0.1;
+        let ray = Ray::new(
+            Vec3D::new(0.0, 8.0, 0.0),
+            Vec3D::new(angle.cos() * (k % 9) as f32, -4.0, angle.sin() * (k % 5) as f32),
+        );
+        let mut expected = HitRecord::new();
+        let mut actual = HitRecord::new();
+        assert_eq!(
+            list.hit(&ray, 0.001, f32::MAX, &mut expected),
+            bvh.hit(&ray, 0.001, f32::MAX, &mut actual)
+        );
+        assert_eq!(expected.t, actual.t);
+        assert_eq!(expected.point, actual.point);
+    }
+}
+
+#[test]
+fn tile_renderer_is_deterministic() {
+    use crate::grfx::color::Color;
+    use crate::grfx::render::TileRenderer;
+    use rand::Rng;
+    let shade = |x: u32, y: u32, rng: &mut dyn rand::RngCore| {
+        Color::rgb(x as u8, y as u8, rng.gen::<u8>())
+    };
+    let single = TileRenderer { tile_size: 7, threads: 1, seed: 42 }.render(37, 23, shade);
+    let multi = TileRenderer { tile_size: 16, threads: 4, seed: 42 }.render(37, 23, shade);
+    assert_eq!(single.len(), 37 * 23);
+    for (index, (a, b)) in single.iter().zip(&multi).enumerate() {
+        assert_eq!(a.as_bytes(), b.as_bytes());
+        assert_eq!(a.r() as usize, index % 37);
+        assert_eq!(a.g() as usize, index / 37);
+    }
+}
+
+#[test]
+fn color_add_saturates() {
+    use crate::grfx::color::{Color, WHITE};
+    let sum = WHITE + Color::rgb(10, 20, 30);
+    assert_eq!(sum.as_bytes(), [255, 255, 255, 255]);
+}
+
+#[test]
+fn rgb_quantized_at_output() {
+    use crate::grfx::color::Rgb;
+    // a bright light times a dark albedo stays exact until quantized
+    let light = Rgb::new(4.0, 0.25, 0.0) * Rgb::new(0.1, 1.0, 0.5);
+    assert_eq!(light.to_color(1.0).as_bytes(), [102, 63, 0, 255]);
+    assert_eq!(Rgb::new(2.0, -1.0, 0.25).to_color(2.0).as_bytes(), [255, 0, 127, 255]);
+}
+
+#[test]
+fn scene_round_trip() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        # three spheres
+        settings width=320 height=200 samples=8 seed=7
+        background solid color=0.1,0.2,0.3
+        camera from=6,1,2 at=4,1,1 up=0,1,0 fov=60 aperture=0.01
+        material ground lambertian albedo=0.5,0.5,0.5
+        material mirror metal albedo=0.7,0.6,0.5 fuzz=0.25
+        material glass dielectric ior=1.5
+        sphere center=0,-1000,0 radius=1000 material=ground
+        sphere center=4,1,0 radius=1 material=mirror  # trailing comment
+        sphere center=0,1,0 radius=1 material=glass
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.settings.width, 320);
+    assert_eq!(scene.settings.samples_per_pixel, 8);
+    assert_eq!(scene.materials.len(), 3);
+    assert_eq!(scene.shapes.len(), 3);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn scene_errors_report_line() {
+    use crate::grfx::scene::Scene;
+    let unknown = Scene::parse("material red lambertian albedo=1,0,0\n\nsphere center=0,0,0 radius=1 material=blue");
+    assert_eq!(unknown.unwrap_err().to_string(), "line 3: unknown material 'blue'");
+    let malformed = Scene::parse("# radius\nsphere center=0,0 radius=1 material=red");
+    assert_eq!(malformed.unwrap_err().line, 2);
+    let typo = Scene::parse("camera fov=abc");
+    assert_eq!(typo.unwrap_err().to_string(), "line 1: invalid value 'abc' for fov");
+}
+
+#[test]
+fn image_encodes_binary_ppm_and_png() {
+    use crate::grfx::color::{Color, BLUE, RED};
+    use crate::grfx::image::{Image, ImageFormat};
+    let image = Image::from_colors(2, 1, vec![RED, BLUE]);
+    let mut ppm = Vec::new();
+    image.encode(&mut ppm, ImageFormat::Ppm).unwrap();
+    assert_eq!(ppm, b"P6\n2 1\n255\n\xff\x00\x00\x00\x00\xff".to_vec());
+
+    let mut png = Vec::new();
+    image.encode(&mut png, ImageFormat::Png).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // color type (byte 25 of the header) is RGB when opaque and RGBA otherwise
+    assert_eq!(png[25], 2);
+    let translucent = Image::from_colors(1, 1, vec![Color::rgba(1, 2, 3, 4)]);
+    let mut png = Vec::new();
+    translucent.encode(&mut png, ImageFormat::Png).unwrap();
+    assert_eq!(png[25], 6);
+
+    assert_eq!(ImageFormat::from_filename("out/render.PNG"), Some(ImageFormat::Png));
+    assert_eq!(ImageFormat::from_filename("render.ppm"), Some(ImageFormat::Ppm));
+    assert_eq!(ImageFormat::from_filename("render"), None);
+}
+
+#[test]
+#[should_panic]
+fn hdr_image_get_checks_bounds() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::image::HdrImage;
+    let image = HdrImage::from_pixels(2, 2, vec![Rgb::BLACK; 4]);
+    // would be the first pixel of the second row without the check
+    image.get(2, 0);
+}
+
+#[test]
+fn hdr_image_encodes_pfm_and_exr() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::image::{HdrImage, ImageFormat};
+    let image = HdrImage::from_pixels(1, 2, vec![Rgb::new(4.0, 0.5, 0.0), Rgb::new(0.0, 0.0, 1.0)]);
+    let mut pfm = Vec::new();
+    image.encode(&mut pfm, ImageFormat::Pfm).unwrap();
+    assert_eq!(&pfm[..12], b"PF\n1 2\n-1.0\n");
+    // bottom row first
+    assert_eq!(&pfm[12..24], &[&0f32.to_le_bytes()[..], &0f32.to_le_bytes(), &1f32.to_le_bytes()].concat()[..]);
+    assert_eq!(&pfm[24..28], &4f32.to_le_bytes());
+
+    let mut exr = Vec::new();
+    image.encode(&mut exr, ImageFormat::Exr).unwrap();
+    assert_eq!(&exr[..4], &[0x76, 0x2f, 0x31, 0x01]);
+    // 2 lines of (y, size, 3 floats), the offset table points at each of them
+    let lines_start = exr.len() - 2 * (8 + 12);
+    let offset = |i: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&exr[lines_start - 16 + 8 * i..lines_start - 8 + 8 * i]);
+        u64::from_le_bytes(bytes) as usize
+    };
+    assert_eq!(offset(0), lines_start);
+    assert_eq!(offset(1), lines_start + 20);
+    // channels in B, G, R order
+    assert_eq!(&exr[lines_start + 8..lines_start + 20], &[&0f32.to_le_bytes()[..], &0.5f32.to_le_bytes(), &4f32.to_le_bytes()].concat()[..]);
+    assert!(image.encode(Vec::new(), ImageFormat::Png).is_err());
+}
+
+#[test]
+fn image_reads_back_what_it_writes() {
+    use crate::grfx::color::Color;
+    use crate::grfx::image::{Image, ImageFormat};
+    let colors: Vec<Color> = (0..12u8).map(|i| Color::rgb(i * 20, 255 - i, i)).collect();
+    let image = Image::from_colors(4, 3, colors);
+    for format in &[ImageFormat::Png, ImageFormat::Ppm, ImageFormat::PpmAscii] {
+        let mut data = Vec::new();
+        image.encode(&mut data, *format).unwrap();
+        let read = Image::decode(&data[..]).unwrap();
+        assert_eq!((read.width(), read.height()), (4, 3));
+        for y in 0..3 {
+            for x in 0..4 {
+                assert_eq!(read.get(x, y).as_bytes(), image.get(x, y).as_bytes());
+            }
+        }
+    }
+}
+
+#[test]
+fn image_reads_ppm_with_comments_and_max_value() {
+    use crate::grfx::image::Image;
+    let data = b"P3 # ascii\n# size\n2 1\n15\n15 0 0  0 15 15\n";
+    let image = Image::decode(&data[..]).unwrap();
+    assert_eq!(image.get(0, 0).as_bytes(), [255, 0, 0, 255]);
+    assert_eq!(image.get(1, 0).as_bytes(), [0, 255, 255, 255]);
+    assert!(Image::decode(&b"P6\n2 2\n255\n\x00\x01"[..]).is_err());
+    assert!(Image::decode(&b"P6 4294967295 4294967295 255\n"[..]).is_err());
+    assert!(Image::decode(&b"GIF89a"[..]).is_err());
+}
+
+#[test]
+fn diffuse_light_is_seen_through_the_camera() {
+    use crate::grfx::camera::Camera;
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::DiffuseLight;
+    use crate::grfx::render::{Background, RenderSettings, Renderer};
+    use crate::grfx::shape::{HitList, Sphere};
+    use std::sync::Arc;
+    let mut world = HitList::new();
+    let light = Arc::new(DiffuseLight::new(Rgb::new(3.0, 0.5, 0.0)));
+    world.add(Arc::new(Sphere::new(Vec3D::new(0.0, 0.0, -5.0), 100.0, light)));
+    let camera = Camera::new(
+        Vec3D::new(0.0, 0.0, 0.0),
+        Vec3D::new(0.0, 0.0, -1.0),
+        Vec3D::new(0.0, 1.0, 0.0),
+        10.0,
+        1.0,
+        0.0,
+        1.0,
+    );
+    let settings = RenderSettings {
+        width: 2,
+        height: 2,
+        samples_per_pixel: 4,
+        background: Background::Solid(Rgb::BLACK),
+        ..RenderSettings::default()
+    };
+    let image = Renderer::new(settings).render_hdr(&world, &camera);
+    // light above 1.0 is kept as is, the light doesn't reflect anything
+    assert_eq!(image.get(1, 1), Rgb::new(3.0, 0.5, 0.0));
+}
+
+#[test]
+fn scene_light_round_trips() {
+    use crate::grfx::scene::Scene;
+    let scene = Scene::parse("material lamp light emit=4,4,4").unwrap();
+    assert_eq!(scene.materials.len(), 1);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn triangle_hit_interpolates_normals_and_uvs() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::Lambertian;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, Triangle};
+    use std::sync::Arc;
+    let material = Arc::new(Lambertian::new(Rgb::WHITE));
+    let triangle = Triangle::new(
+        Vec3D::new(0.0, 0.0, 0.0),
+        Vec3D::new(2.0, 0.0, 0.0),
+        Vec3D::new(0.0, 2.0, 0.0),
+        material.clone(),
+    )
+    .with_uvs([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)])
+    .with_normals([Vec3D::new(0.0, 0.0, 1.0), Vec3D::new(1.0, 0.0, 1.0), Vec3D::new(0.0, 0.0, 1.0)]);
+    let mut record = HitRecord::new();
+    let ray = Ray::new(Vec3D::new(1.0, 0.5, 5.0), Vec3D::new(0.0, 0.0, -1.0));
+    assert!(triangle.hit(&ray, 0.001, f32::MAX, &mut record));
+    assert_eq!(record.t, 5.0);
+    assert_eq!((record.u, record.v), (0.5, 0.25));
+    assert_eq!(record.normal, Vec3D::new(0.5, 0.0, 1.0).unit_vector());
+    assert_eq!(triangle.normal(), Vec3D::new(0.0, 0.0, 1.0));
+
+    // outside, parallel (grazing) and degenerate triangles are missed
+    let outside = Ray::new(Vec3D::new(1.5, 1.5, 5.0), Vec3D::new(0.0, 0.0, -1.0));
+    assert!(!triangle.hit(&outside, 0.001, f32::MAX, &mut record));
+    let grazing = Ray::new(Vec3D::new(-1.0, 0.5, 0.0), Vec3D::new(1.0, 0.0, 0.0));
+    assert!(!triangle.hit(&grazing, 0.001, f32::MAX, &mut record));
+    let degenerate = Triangle::new(
+        Vec3D::new(0.0, 0.0, 0.0),
+        Vec3D::new(1.0, 1.0, 0.0),
+        Vec3D::new(2.0, 2.0, 0.0),
+        material,
+    );
+    let through = Ray::new(Vec3D::new(1.0, 1.0, 5.0), Vec3D::new(0.0, 0.0, -1.0));
+    assert!(!degenerate.hit(&through, 0.001, f32::MAX, &mut record));
+    assert_eq!(degenerate.normal(), Vec3D::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn scene_triangle_round_trips() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        material red lambertian albedo=1,0,0
+        triangle a=0,0,0 b=1,0,0 c=0,1,0.5 material=red
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.shapes.len(), 1);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn obj_mesh_loads_faces_and_materials() {
+    use crate::grfx::obj::Mesh;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable};
+    use std::io;
+    let obj = "\
+mtllib shiny.mtl
+v 0 0 0
+v 2 0 0
+v 2 2 0
+v 0 2 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl gold # quad split in two triangles
+f 1/1/1 2/2/1 3/3/1 -1/-1/-1
+";
+    let mtl = "newmtl gold\nKd 0.1 0.1 0.1\nKs 1 0.8 0.3\nNs 500\n";
+    let mesh = Mesh::parse(obj, |name| {
+        assert_eq!(name, "shiny.mtl");
+        Ok::<_, io::Error>(mtl.to_string())
+    })
+    .unwrap();
+    assert_eq!(mesh.len(), 2);
+
+    let mut record = HitRecord::new();
+    let ray = Ray::new(Vec3D::new(0.5, 1.5, 5.0), Vec3D::new(0.0, 0.0, -1.0));
+    assert!(mesh.hit(&ray, 0.001, f32::MAX, &mut record));
+    assert_eq!(record.t, 5.0);
+    assert_eq!((record.u, record.v), (0.25, 0.75));
+    assert_eq!(record.normal, Vec3D::new(0.0, 0.0, 1.0));
+}
+
+#[test]
+fn obj_errors_report_line() {
+    use crate::grfx::obj::Mesh;
+    let no_library = |_: &str| Ok(String::new());
+    let error = Mesh::parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n", no_library).err().unwrap();
+    assert_eq!(error.to_string(), "line 3: invalid vertex index '3' in '3'");
+    let error = Mesh::parse("v 0 0\n", no_library).err().unwrap();
+    assert_eq!(error.to_string(), "line 1: 'v' expects 3 numbers but got 2");
+    // only positions and texture coordinates take an extra (w) component, which must be a number
+    assert!(Mesh::parse("v 0 0 0 1\nvt 0 0 1\n", no_library).is_ok());
+    let error = Mesh::parse("v 0 0 0 x\n", no_library).err().unwrap();
+    assert_eq!(error.to_string(), "line 1: invalid number 'x' for 'v'");
+    let error = Mesh::parse("vn 0 0 1 9\n", no_library).err().unwrap();
+    assert_eq!(error.to_string(), "line 1: 'vn' expects 3 numbers but got 4");
+    let error = Mesh::parse("mtllib a.mtl\n", |_| Ok("newmtl red\nKd 1 2 3 4\n".to_string()))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "a.mtl: line 2: 'Kd' expects 3 numbers but got 4");
+    let error = Mesh::parse("v 0 0 0\nusemtl glass\n", no_library).err().unwrap();
+    assert_eq!(error.to_string(), "line 2: unknown material 'glass'");
+    let error = Mesh::parse("mtllib a.mtl\n", |_| Ok("Kd 1 1 1\n".to_string()))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "a.mtl: line 1: 'Kd' before any newmtl");
+}
+
+#[test]
+fn plane_disk_and_rect_hits() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::Lambertian;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{Disk, HitRecord, Hitable, Plane, Rect};
+    use std::sync::Arc;
+    let material = Arc::new(Lambertian::new(Rgb::WHITE));
+    let down = Ray::new(Vec3D::new(2.25, 5.0, -3.5), Vec3D::new(0.0, -1.0, 0.0));
+    let mut record = HitRecord::new();
+
+    let floor = Plane::new(Vec3D::new(0.0, 1.0, 0.0), Vec3D::new(0.0, 2.0, 0.0), material.clone());
+    assert!(floor.hit(&down, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.normal), (4.0, Vec3D::new(0.0, 1.0, 0.0)));
+    assert_eq!((record.u, record.v), (0.25, 0.5));
+    assert!(floor.bounding_box().is_none());
+    assert!(!floor.hit(&Ray::new(Vec3D::new(0.0, 5.0, 0.0), Vec3D::new(1.0, 0.0, 0.0)), 0.001, f32::MAX, &mut record));
+
+    let disk = Disk::new(Vec3D::new(2.0, 0.0, -3.5), Vec3D::new(0.0, 1.0, 0.0), 0.5, material.clone());
+    assert!(disk.hit(&down, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.v), (5.0, 0.5));
+    let bbox = disk.bounding_box().unwrap();
+    assert!((bbox.max().x() - 2.5).abs() < 1e-3 && (bbox.max().y() - 0.0).abs() < 1e-3);
+    let outside = Ray::new(Vec3D::new(2.6, 5.0, -3.5), Vec3D::new(0.0, -1.0, 0.0));
+    assert!(!disk.hit(&outside, 0.001, f32::MAX, &mut record));
+
+    // rectangle from x 2 to 3 and z -4 to -3 at y = 1
+    let rect = Rect::xz(3.0, 2.0, -4.0, -3.0, 1.0, material);
+    assert!(rect.hit(&down, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.normal), (4.0, Vec3D::new(0.0, 1.0, 0.0)));
+    assert_eq!((record.u, record.v), (0.25, 0.5));
+    let beside = Ray::new(Vec3D::new(3.5, 5.0, -3.5), Vec3D::new(0.0, -1.0, 0.0));
+    assert!(!rect.hit(&beside, 0.001, f32::MAX, &mut record));
+    assert!(rect.bounding_box().unwrap().hit(&down, 0.001, f32::MAX));
+}
+
+#[test]
+fn scene_plane_disk_and_rect_round_trip() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        material red lambertian albedo=1,0,0
+        plane point=0,0,0 normal=0,1,0 material=red
+        disk center=0,3,0 normal=0,-1,0 radius=0.5 material=red
+        rect x=-1,1 y=2.5 z=0,2 material=red
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.shapes.len(), 3);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn box_cylinder_cone_and_torus_hits() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::Lambertian;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{Cone, Cuboid, Cylinder, HitRecord, Hitable, Torus};
+    use std::sync::Arc;
+    let material = Arc::new(Lambertian::new(Rgb::WHITE));
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+    let mut record = HitRecord::new();
+    let side = Ray::new(Vec3D::new(-5.0, 0.5, 0.0), Vec3D::new(2.0, 0.0, 0.0));
+    let down = Ray::new(Vec3D::new(0.0, 5.0, 0.0), Vec3D::new(0.0, -1.0, 0.0));
+
+    let cuboid = Cuboid::new(Vec3D::new(1.0, 2.0, 1.0), Vec3D::new(-1.0, 0.0, -1.0), material.clone());
+    assert!(cuboid.hit(&side, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.normal), (2.0, Vec3D::new(-1.0, 0.0, 0.0)));
+    assert_eq!((record.u, record.v), (0.25, 0.5));
+    // from the inside the ray hits the face where it leaves
+    let inside = Ray::new(Vec3D::new(0.0, 1.0, 0.0), Vec3D::new(0.0, 0.0, 1.0));
+    assert!(cuboid.hit(&inside, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.normal), (1.0, Vec3D::new(0.0, 0.0, 1.0)));
+    // flat boxes don't give NaN coordinates
+    let flat = Cuboid::new(Vec3D::new(-1.0, 0.0, 0.0), Vec3D::new(1.0, 2.0, 0.0), material.clone());
+    let along = Ray::new(Vec3D::new(-5.0, 0.5, 0.0), Vec3D::new(2.0, 0.0, 0.0));
+    assert!(flat.hit(&along, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.u, record.v), (2.0, 0.25, 0.0));
+    assert!(flat.bounding_box().unwrap().hit(&along, 0.001, f32::MAX));
+
+    let cylinder = Cylinder::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, 2.0, material.clone());
+    assert!(cylinder.hit(&side, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.normal, record.v), (2.0, Vec3D::new(-1.0, 0.0, 0.0), 0.25));
+    assert!(cylinder.hit(&down, 0.001, f32::MAX, &mut record));
+    assert_eq!((record.t, record.normal), (3.0, Vec3D::new(0.0, 1.0, 0.0)));
+    let above = Ray::new(Vec3D::new(-5.0, 2.5, 0.0), Vec3D::new(1.0, 0.0, 0.0));
+    assert!(!cylinder.hit(&above, 0.001, f32::MAX, &mut record));
+
+    // halfway up the side the cone is half as wide, the normal leans up by 45 degrees
+    let cone = Cone::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, 1.0, material.clone());
+    assert!(cone.hit(&side, 0.001, f32::MAX, &mut record));
+    assert!(close(record.t, 2.25) && close(record.v, 0.5));
+    assert!((record.normal - Vec3D::new(-1.0, 1.0, 0.0).unit_vector()).length() < 1e-4);
+    assert!(cone.hit(&down, 0.001, f32::MAX, &mut record));
+    assert!(close(record.t, 4.0));
+    assert!(cone.bounding_box().unwrap().hit(&down, 0.001, f32::MAX));
+
+    // the side ray crosses the tube twice on each side, the down ray goes through the hole
+    let torus = Torus::new(Vec3D::new(0.0, 0.5, 0.0), 2.0, 0.5, material);
+    assert!(torus.hit(&side, 0.001, f32::MAX, &mut record));
+    assert!(close(record.t, 1.25) && close(record.v, 0.0));
+    assert!((record.normal - Vec3D::new(-1.0, 0.0, 0.0)).length() < 1e-4);
+    assert!(torus.hit(&side, 1.5, f32::MAX, &mut record));
+    assert!(close(record.t, 1.75) && close(record.v, 0.5));
+    assert!(!torus.hit(&down, 0.001, f32::MAX, &mut record));
+    let far = Ray::new(Vec3D::new(-1000.0, 0.5, 0.0), Vec3D::new(1.0, 0.0, 0.0));
+    assert!(torus.hit(&far, 0.001, f32::MAX, &mut record));
+    assert!((record.point - Vec3D::new(-2.5, 0.5, 0.0)).length() < 1e-3);
+}
+
+#[test]
+fn scene_box_cylinder_cone_and_torus_round_trip() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        material red lambertian albedo=1,0,0
+        box min=-1,0,-1 max=1,2,1 material=red
+        cylinder base=0,0,0 radius=1 height=2 material=red
+        cone base=3,0,0 radius=1 height=2 material=red
+        torus center=0,1,0 major=1 minor=0.25 material=red
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.shapes.len(), 4);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn scene_rejects_flat_boxes_and_cones() {
+    use crate::grfx::scene::Scene;
+    let flat = Scene::parse("material red lambertian albedo=1,0,0\nbox min=0,0,0 max=1,0,1 material=red");
+    assert_eq!(flat.unwrap_err().to_string(), "line 2: box size can't be 0 along any axis");
+    let flat = Scene::parse("material red lambertian albedo=1,0,0\ncone base=0,0,0 radius=1 height=0 material=red");
+    assert_eq!(flat.unwrap_err().to_string(), "line 2: cone height can't be 0");
+}
+
+#[test]
+fn matrix_inverse_and_transform_order() {
+    use crate::grfx::transform::{Mat4, Transform};
+    let transform = Transform::identity()
+        .scale(Vec3D::new(2.0, 1.0, 1.0))
+        .rotate_z(90.0)
+        .translate(Vec3D::new(0.0, 0.0, 5.0));
+    // (1, 0, 0) is scaled to (2, 0, 0), rotated to (0, 2, 0) and moved to (0, 2, 5)
+    let point = transform.point(&Vec3D::new(1.0, 0.0, 0.0));
+    assert!((point - Vec3D::new(0.0, 2.0, 5.0)).length() < 1e-6);
+    assert!((transform.vector(&Vec3D::new(0.0, 0.0, 1.0)) - Vec3D::new(0.0, 0.0, 1.0)).length() < 1e-6);
+
+    let inverse = transform.matrix().inverse().unwrap();
+    for row in 0..4 {
+        for column in 0..4 {
+            assert!((inverse.get(row, column) - transform.inverse().get(row, column)).abs() < 1e-6);
+        }
+    }
+    let identity = *transform.matrix() * *transform.inverse();
+    assert!((identity.transform_point(&Vec3D::new(3.0, -2.0, 1.0)) - Vec3D::new(3.0, -2.0, 1.0)).length() < 1e-5);
+    assert!(Mat4::scaling(Vec3D::new(1.0, 0.0, 1.0)).inverse().is_none());
+    assert!(Transform::from_matrix(Mat4::translation(Vec3D::new(1.0, 2.0, 3.0))).is_some());
+}
+
+#[test]
+fn instance_moves_rays_and_normals() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::Lambertian;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, Sphere};
+    use crate::grfx::transform::{Instance, Transform};
+    use std::sync::Arc;
+    let sphere = Arc::new(Sphere::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Rgb::WHITE))));
+    // squashed to half height and moved to (0, 0, -10)
+    let transform = Transform::identity()
+        .scale(Vec3D::new(2.0, 0.5, 2.0))
+        .translate(Vec3D::new(0.0, 0.0, -10.0));
+    let instance = Instance::new(sphere, transform);
+    let bbox = instance.bounding_box().unwrap();
+    assert!((bbox.min() - Vec3D::new(-2.0, -0.5, -12.0)).length() < 1e-5);
+    assert!((bbox.max() - Vec3D::new(2.0, 0.5, -8.0)).length() < 1e-5);
+
+    let mut record = HitRecord::new();
+    let ray = Ray::new(Vec3D::new(0.0, 0.0, 0.0), Vec3D::new(0.0, 0.0, -1.0));
+    assert!(instance.hit(&ray, 0.001, f32::MAX, &mut record));
+    assert!((record.t - 8.0).abs() < 1e-5);
+    assert!((record.point - Vec3D::new(0.0, 0.0, -8.0)).length() < 1e-5);
+    assert!((record.normal - Vec3D::new(0.0, 0.0, 1.0)).length() < 1e-5);
+    // on the squashed side the normal leans much more up than the point does
+    let above = Ray::new(Vec3D::new(1.0, 5.0, -10.0), Vec3D::new(0.0, -1.0, 0.0));
+    assert!(instance.hit(&above, 0.001, f32::MAX, &mut record));
+    let expected = Vec3D::new(0.5 / 2.0, 0.75f32.sqrt() / 0.5, 0.0).unit_vector();
+    assert!((record.normal - expected).length() < 1e-4);
+    let beside = Ray::new(Vec3D::new(0.0, 0.6, 0.0), Vec3D::new(0.0, 0.0, -1.0));
+    assert!(!instance.hit(&beside, 0.001, f32::MAX, &mut record));
+}
+
+#[test]
+fn moving_sphere_follows_ray_time() {
+    use crate::grfx::camera::Camera;
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::Lambertian;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, MovingSphere};
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    let material = Arc::new(Lambertian::new(Rgb::WHITE));
+    let sphere = MovingSphere::new(Vec3D::new(0.0, 0.0, -5.0), 0.0, Vec3D::new(4.0, 0.0, -5.0), 1.0, 0.5, material);
+    assert_eq!(sphere.center(0.25), Vec3D::new(1.0, 0.0, -5.0));
+    let bbox = sphere.bounding_box().unwrap();
+    assert_eq!((bbox.min().x(), bbox.max().x()), (-0.5, 4.5));
+
+    let mut record = HitRecord::new();
+    let forward = Vec3D::new(0.0, 0.0, -1.0);
+    assert!(sphere.hit(&Ray::new(Vec3D::new(0.0, 0.0, 0.0), forward), 0.001, f32::MAX, &mut record));
+    assert!(!sphere.hit(&Ray::with_time(Vec3D::new(0.0, 0.0, 0.0), forward, 0.5), 0.001, f32::MAX, &mut record));
+    assert!(sphere.hit(&Ray::with_time(Vec3D::new(2.0, 0.0, 0.0), forward, 0.5), 0.001, f32::MAX, &mut record));
+    assert_eq!(record.t, 4.5);
+
+    // camera rays are spread over the time the shutter is open
+    let camera = Camera::new(Vec3D::new(0.0, 0.0, 0.0), forward, Vec3D::new(0.0, 1.0, 0.0), 90.0, 1.0, 0.0, 1.0)
+        .with_shutter(0.25, 0.75);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+    let times: Vec<f32> = (0..100).map(|_| camera.ray(0.5, 0.5, &mut rng).time()).collect();
+    assert!(times.iter().all(|time| (0.25..0.75).contains(time)));
+    assert!(times.iter().any(|time| *time < 0.4) && times.iter().any(|time| *time > 0.6));
+}
+
+#[test]
+fn moving_sphere_stays_put_outside_its_motion() {
+    use crate::grfx::bvh::BvhNode;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::scene::Scene;
+    use crate::grfx::shape::{HitRecord, Hitable};
+    // moving spheres move between times 0 and 1, the shutter stays open longer
+    let scene = Scene::parse("
+        camera from=0,0,0 at=0,0,-1 shutter=0,2
+        material white lambertian albedo=1,1,1
+        moving_sphere from=0,0,-5 to=4,0,-5 radius=0.5 material=white
+        sphere center=-4,0,-5 radius=0.5 material=white
+    ").unwrap();
+    let world = BvhNode::new(scene.world().unwrap());
+    let forward = Vec3D::new(0.0, 0.0, -1.0);
+    let mut record = HitRecord::new();
+    // the sphere waits at its end, inside its bounding box
+    assert!(world.hit(&Ray::with_time(Vec3D::new(4.0, 0.0, 0.0), forward, 1.5), 0.001, f32::MAX, &mut record));
+    assert_eq!(record.t, 4.5);
+    assert!(!world.hit(&Ray::with_time(Vec3D::new(6.0, 0.0, 0.0), forward, 1.5), 0.001, f32::MAX, &mut record));
+    assert!(world.hit(&Ray::with_time(Vec3D::new(0.0, 0.0, 0.0), forward, -1.0), 0.001, f32::MAX, &mut record));
+}
+
+#[test]
+fn scene_shutter_and_moving_sphere_round_trip() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        camera from=6,1,2 at=4,1,1 shutter=0,0.5
+        material red lambertian albedo=1,0,0
+        moving_sphere from=2,0.5,2 to=2,1,2 radius=0.5 material=red
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.shapes.len(), 1);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn textures_color_materials() {
+    use crate::grfx::color::{Color, Rgb};
+    use crate::grfx::image::Image;
+    use crate::grfx::material::{Lambertian, Material};
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::HitRecord;
+    use crate::grfx::texture::{Checker, ImageTexture, Texture};
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    let (dark, light) = (Rgb::new(0.1, 0.1, 0.1), Rgb::new(0.9, 0.9, 0.9));
+    let checker = Checker::colors(dark, light, 2.0);
+    assert_eq!(checker.value(0.0, 0.0, &Vec3D::new(0.5, 0.5, 0.5)), dark);
+    assert_eq!(checker.value(0.0, 0.0, &Vec3D::new(2.5, 0.5, 0.5)), light);
+    assert_eq!(checker.value(0.0, 0.0, &Vec3D::new(-0.5, 0.5, 0.5)), light);
+    // points on the floor (y = 0) don't flicker between cells because of rounding
+    assert_eq!(checker.value(0.0, 0.0, &Vec3D::new(0.5, -1e-7, 0.5)), dark);
+
+    // 2x2 image, (0, 0) is the bottom left corner
+    let pixels = vec![Color::rgb(255, 0, 0), Color::rgb(0, 255, 0), Color::rgb(0, 0, 255), Color::rgb(128, 128, 128)];
+    let texture = ImageTexture::new(Image::from_colors(2, 2, pixels));
+    assert_eq!(texture.value(0.25, 0.75, &Vec3D::default()), Rgb::new(1.0, 0.0, 0.0));
+    assert_eq!(texture.value(0.1, 0.1, &Vec3D::default()), Rgb::new(0.0, 0.0, 1.0));
+    assert_eq!(texture.value(1.0, 0.0, &Vec3D::default()).r(), (128.0f32 / 255.0).powf(2.0));
+
+    // materials take the color of the texture where they're hit
+    let material = Lambertian::textured(Arc::new(checker));
+    let mut record = HitRecord::new();
+    record.point = Vec3D::new(2.5, 0.0, 0.5);
+    record.normal = Vec3D::new(0.0, 1.0, 0.0);
+    let ray = Ray::new(Vec3D::new(2.5, 1.0, 0.5), Vec3D::new(0.0, -1.0, 0.0));
+    let mut attenuation = Rgb::BLACK;
+    let mut scattered = Ray::default();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    assert!(material.scatter(&ray, &record, &mut attenuation, &mut scattered, &mut rng));
+    assert_eq!(attenuation, light);
+}
+
+#[test]
+fn scene_textures_round_trip() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        texture checks checker even=0.2,0.3,0.1 odd=0.9,0.9,0.9 scale=0.5
+        texture earth image file=earth.png
+        material floor lambertian texture=checks
+        material globe metal texture=earth
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.textures.len(), 2);
+    assert_eq!(scene.materials.len(), 2);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn scene_texture_errors() {
+    use crate::grfx::scene::Scene;
+    let texture = Scene::parse("material red lambertian texture=bricks");
+    assert_eq!(texture.unwrap_err().to_string(), "line 1: unknown texture 'bricks'");
+    // image textures are only read when creating the world
+    let missing = Scene::parse("texture bricks image file=does/not/exist.png").unwrap();
+    assert!(missing.world().err().unwrap().to_string().starts_with("does/not/exist.png: "));
+}
+
+#[test]
+fn perlin_noise_is_seeded_and_bounded() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::perlin::Perlin;
+    use crate::grfx::texture::{NoisePattern, NoiseTexture, Texture};
+    let perlin = Perlin::new(42);
+    let points: Vec<Vec3D> = (0..200)
+        .map(|i| Vec3D::new(i as f32 * 0.37, (i % 7) as f32 * 1.3 - 4.0, (i % 13) as f32 * -0.71))
+        .collect();
+    let values: Vec<f32> = points.iter().map(|point| perlin.noise(point)).collect();
+    assert!(values.iter().all(|value| (-1.0..=1.0).contains(value)));
+    assert!(values.iter().any(|value| *value > 0.1) && values.iter().any(|value| *value < -0.1));
+    // same seed same noise, other seeds differ
+    let again: Vec<f32> = points.iter().map(|point| Perlin::new(42).noise(point)).collect();
+    assert_eq!(values, again);
+    let other: Vec<f32> = points.iter().map(|point| Perlin::new(43).noise(point)).collect();
+    assert_ne!(values, other);
+    // gradient noise vanishes at the lattice points and is continuous
+    assert_eq!(perlin.noise(&Vec3D::new(3.0, -2.0, 5.0)), 0.0);
+    let near = perlin.noise(&Vec3D::new(0.5, 0.5, 0.5)) - perlin.noise(&Vec3D::new(0.501, 0.5, 0.5));
+    assert!(near.abs() < 0.01);
+
+    for point in &points {
+        let turbulence = perlin.turbulence(point, 7);
+        assert!((0.0..=2.0).contains(&turbulence));
+        assert!((-1.0..=1.0).contains(&perlin.fractal(point, 7)));
+    }
+    // textures blend between their two colors
+    let (low, high) = (Rgb::new(0.2, 0.1, 0.0), Rgb::new(0.8, 0.6, 0.4));
+    let wood = NoiseTexture::new(1, NoisePattern::Wood, 2.0).with_colors(low, high);
+    for point in &points {
+        let color = wood.value(0.0, 0.0, point);
+        assert!(color.r() >= 0.2 - 1e-6 && color.r() <= 0.8 + 1e-6);
+        assert!((color.g() - 0.1 - (color.r() - 0.2) * 5.0 / 6.0).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn scene_noise_texture_round_trips() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        texture stone noise pattern=marble scale=4 seed=9 high=0.9,0.9,0.8
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.textures.len(), 1);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn constant_medium_scatters_inside_boundary() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::{Isotropic, Lambertian, Material};
+    use crate::grfx::medium::ConstantMedium;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, Sphere};
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    let boundary = Arc::new(Sphere::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Rgb::WHITE))));
+    let phase : Arc<dyn Material> = Arc::new(Isotropic::new(Rgb::new(0.5, 0.5, 0.5)));
+    let thick = ConstantMedium::new(boundary.clone(), 1e4, phase.clone());
+    let thin = ConstantMedium::new(boundary, 1e-6, phase);
+    let ray = Ray::new(Vec3D::new(0.0, 0.0, -5.0), Vec3D::new(0.0, 0.0, 2.0));
+    let mut record = HitRecord::new();
+    // dense media scatter right where rays enter, thin ones let them through
+    assert!(thick.hit(&ray, 0.001, f32::MAX, &mut record));
+    assert!(record.t >= 2.0 && record.t < 2.01, "t = {}", record.t);
+    assert!(!thin.hit(&ray, 0.001, f32::MAX, &mut record));
+    // same ray, same distance
+    let mut again = HitRecord::new();
+    thick.hit(&ray, 0.001, f32::MAX, &mut again);
+    assert_eq!(again.t, record.t);
+    // rays starting inside scatter ahead of their origin
+    let inside = Ray::new(Vec3D::new(0.0, 0.5, 0.0), Vec3D::new(1.0, 0.0, 0.0));
+    assert!(thick.hit(&inside, 0.001, f32::MAX, &mut record));
+    assert!(record.t >= 0.001 && record.t < 0.01);
+    assert!(!thick.hit(&Ray::new(Vec3D::new(0.0, 5.0, 0.0), Vec3D::new(1.0, 0.0, 0.0)), 0.001, f32::MAX, &mut record));
+
+    let mut attenuation = Rgb::BLACK;
+    let mut scattered = Ray::default();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    assert!(record.material.scatter(&inside, &record, &mut attenuation, &mut scattered, &mut rng));
+    assert_eq!(attenuation, Rgb::new(0.5, 0.5, 0.5));
+    assert!((scattered.direction().length() - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn medium_distances_vary_with_wavelength() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::{Isotropic, Lambertian};
+    use crate::grfx::medium::ConstantMedium;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, Sphere};
+    use std::sync::Arc;
+    let boundary = Arc::new(Sphere::new(Vec3D::new(0.0, 0.0, 0.0), 100.0, Arc::new(Lambertian::new(Rgb::WHITE))));
+    let fog = ConstantMedium::new(boundary, 0.1, Arc::new(Isotropic::new(Rgb::WHITE)));
+    let distance = |wavelength: Option<f32>| {
+        let ray = Ray::new(Vec3D::new(0.0, 0.0, 0.0), Vec3D::new(0.0, 0.0, 1.0)).with_wavelength(wavelength);
+        let mut record = HitRecord::new();
+        assert!(fog.hit(&ray, 0.001, f32::MAX, &mut record));
+        record.t
+    };
+    // the spectral samples of a path don't all scatter at the same place
+    let distances: Vec<f32> = [None, Some(450.0), Some(550.0), Some(650.0)].iter().map(|w| distance(*w)).collect();
+    for (i, a) in distances.iter().enumerate() {
+        assert!(distances[i + 1..].iter().all(|b| a != b), "{:?}", distances);
+    }
+    assert_eq!(distance(Some(550.0)), distances[2]);
+}
+
+#[test]
+fn scene_medium_round_trips() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        material smoke isotropic albedo=0.2,0.2,0.2
+        medium density=0.5 box min=-2,0,-2 max=2,1,2 material=smoke
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.shapes.len(), 1);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn scene_medium_errors() {
+    use crate::grfx::scene::Scene;
+    let medium = Scene::parse("material fog isotropic albedo=1,1,1\nmedium density=1 material=fog");
+    assert_eq!(medium.unwrap_err().to_string(), "line 2: expected 'medium density=<value> <shape>'");
+    // open or concave boundaries would let rays in without finding where they leave
+    let boundaries = [
+        "plane point=0,0,0 normal=0,1,0",
+        "torus center=0,0,0 major=1 minor=0.2",
+        "medium density=1 sphere center=0,0,0 radius=1",
+    ];
+    for boundary in boundaries {
+        let text = format!("material fog isotropic albedo=1,1,1\nmedium density=1 {} material=fog", boundary);
+        let error = Scene::parse(&text).unwrap_err().to_string();
+        assert!(error.starts_with("line 2: medium boundary has to be a closed convex shape"), "{}", error);
+    }
+}
+
+#[test]
+fn renders_are_reproducible_from_the_seed() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::render::Renderer;
+    use crate::grfx::scene::Scene;
+    // every random choice: lens, shutter, diffuse, fuzzy and glass bounces and media
+    let scene = Scene::parse("
+        settings width=12 height=8 samples=4 depth=8 tile=5 seed=11
+        camera from=0,1,4 at=0,0.5,0 fov=50 aperture=0.2 shutter=0,1
+        material ground lambertian albedo=0.5,0.5,0.5
+        material steel metal albedo=0.8,0.8,0.8 fuzz=0.3
+        material glass dielectric ior=1.5
+        material fog isotropic albedo=0.9,0.9,0.9
+        plane point=0,0,0 normal=0,1,0 material=ground
+        moving_sphere from=-1,0.5,0 to=-1,0.8,0 radius=0.5 material=steel
+        sphere center=1,0.5,0 radius=0.5 material=glass
+        medium density=2 sphere center=0,0.5,-1 radius=0.5 material=fog
+    ").unwrap();
+    let world = scene.world().unwrap();
+    let render = |seed: u64, threads: usize| -> Vec<Rgb> {
+        let mut settings = scene.settings.clone();
+        settings.seed = seed;
+        settings.threads = threads;
+        let image = Renderer::new(settings).render_hdr(&world, &scene.camera());
+        (0..8).flat_map(|y| (0..12).map(move |x| (x, y))).map(|(x, y)| image.get(x, y)).collect()
+    };
+    let first = render(11, 1);
+    // bit identical, whatever the number of threads
+    assert_eq!(render(11, 1), first);
+    assert_eq!(render(11, 3), first);
+    assert_ne!(render(12, 1), first);
+}
+
+#[test]
+fn light_sampling_densities_integrate_to_one() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::DiffuseLight;
+    use crate::grfx::shape::{Disk, Hitable, Rect, Sphere, Triangle};
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+    let light = Arc::new(DiffuseLight::new(Rgb::WHITE));
+    let lights: Vec<Box<dyn Hitable>> = vec![
+        Box::new(Sphere::new(Vec3D::new(0.0, 2.0, 0.0), 1.0, light.clone())),
+        Box::new(Rect::xz(-1.0, 1.0, -2.0, 1.0, 1.0, light.clone())),
+        Box::new(Disk::new(Vec3D::new(1.0, 0.0, 1.0), Vec3D::new(-1.0, 0.0, -1.0), 1.0, light.clone())),
+        Box::new(Triangle::new(Vec3D::new(-1.0, -1.0, 1.0), Vec3D::new(1.0, -1.0, 1.0), Vec3D::new(0.0, 1.0, 1.0), light)),
+    ];
+    let origin = Vec3D::new(0.0, 0.0, 0.0);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+    for (index, light) in lights.iter().enumerate() {
+        // integrate the density over all the directions (uniformly sampled)
+        let count = 100_000;
+        let mut sum = 0.0;
+        for _ in 0..count {
+            let z: f32 = rng.gen_range(-1.0..1.0);
+            let phi = rng.gen_range(0.0..2.0 * std::f32::consts::PI);
+            let r = (1.0 - z * z).sqrt();
+            sum += light.pdf_value(&origin, &Vec3D::new(r * phi.cos(), r * phi.sin(), z)) as f64;
+        }
+        let integral = 4.0 * std::f64::consts::PI * sum / count as f64;
+        assert!((integral - 1.0).abs() < 0.03, "light {} integrates to {}", index, integral);
+        // sampled directions point at the light
+        for _ in 0..100 {
+            let direction = light.sample_direction(&origin, &mut rng).unwrap();
+            assert!(light.pdf_value(&origin, &direction) > 0.0, "light {}", index);
+        }
+    }
+    // no sampling from inside a sphere
+    assert!(lights[0].sample_direction(&Vec3D::new(0.0, 2.0, 0.0), &mut rng).is_none());
+}
+
+#[test]
+fn instanced_lights_can_be_sampled() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::DiffuseLight;
+    use crate::grfx::shape::{Hitable, Rect, Sphere};
+    use crate::grfx::transform::{Instance, Transform};
+    use crate::grfx::sampling;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    let light = Arc::new(DiffuseLight::new(Rgb::WHITE));
+    let stretch = Transform::identity()
+        .scale(Vec3D::new(2.0, 0.5, 1.0))
+        .rotate_y(30.0)
+        .translate(Vec3D::new(0.0, 2.0, 0.0));
+    let ball = Sphere::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, light.clone());
+    let panel = Rect::xz(-1.0, 1.0, -1.0, 1.0, 0.0, light);
+    let lights = [Instance::new(Arc::new(ball), stretch), Instance::new(Arc::new(panel), stretch)];
+    let origin = Vec3D::new(0.5, -1.0, 0.0);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(8);
+    for (index, light) in lights.iter().enumerate() {
+        // the world space density still integrates to one over the directions
+        let count = 100_000;
+        let mut sum = 0.0;
+        for _ in 0..count {
+            sum += light.pdf_value(&origin, &sampling::uniform_sphere(&mut rng)) as f64;
+        }
+        let integral = sum / count as f64 / sampling::uniform_sphere_pdf() as f64;
+        assert!((integral - 1.0).abs() < 0.03, "light {} integrates to {}", index, integral);
+        for _ in 0..100 {
+            let direction = light.sample_direction(&origin, &mut rng).unwrap();
+            assert!(light.pdf_value(&origin, &direction) > 0.0, "light {}", index);
+        }
+    }
+}
+
+#[test]
+fn light_sampling_matches_direct_lighting() {
+    use crate::grfx::camera::Camera;
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::{DiffuseLight, Lambertian};
+    use crate::grfx::render::{Background, RenderSettings, Renderer};
+    use crate::grfx::shape::{HitList, Hitable, Plane, Sphere};
+    use std::sync::Arc;
+    // floor seen from above, lit by a small sphere right over it
+    let lamp: Arc<dyn Hitable> = Arc::new(Sphere::new(
+        Vec3D::new(0.0, 2.0, 0.0),
+        0.2,
+        Arc::new(DiffuseLight::new(Rgb::new(10.0, 10.0, 10.0))),
+    ));
+    let mut world = HitList::new();
+    world.add(Arc::new(Plane::new(
+        Vec3D::new(0.0, 0.0, 0.0),
+        Vec3D::new(0.0, 1.0, 0.0),
+        Arc::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(lamp.clone());
+    let camera = Camera::new(
+        Vec3D::new(0.0, 1.0, 0.0),
+        Vec3D::new(0.0, 0.0, 0.0),
+        Vec3D::new(0.0, 0.0, 1.0),
+        1.0,
+        1.0,
+        0.0,
+        1.0,
+    );
+    let settings = RenderSettings {
+        width: 4,
+        height: 4,
+        samples_per_pixel: 16,
+        background: Background::Solid(Rgb::BLACK),
+        ..RenderSettings::default()
+    };
+    // radiance = albedo / pi * irradiance = albedo * emitted * sin² of the light cone half angle
+    let expected = 0.5 * 10.0 * 0.2 * 0.2 / (2.0 * 2.0);
+    let sampled = Renderer::new(settings.clone())
+        .with_lights(vec![lamp])
+        .render_hdr(&world, &camera);
+    for (x, y) in (0..4).flat_map(|y| (0..4).map(move |x| (x, y))) {
+        let value = sampled.get(x, y).r();
+        assert!((value - expected).abs() < 0.05 * expected, "{} instead of {}", value, expected);
+    }
+    // without light sampling only the rare bounces hitting the lamp count, same result on average
+    let settings = RenderSettings { samples_per_pixel: 2000, ..settings };
+    let bounced = Renderer::new(settings).render_hdr(&world, &camera);
+    let mean: f32 = (0..16).map(|i| bounced.get(i % 4, i / 4).r()).sum::<f32>() / 16.0;
+    assert!((mean - expected).abs() < 0.15 * expected, "{} instead of {}", mean, expected);
+}
+
+#[test]
+fn orthonormal_basis_is_orthonormal() {
+    let directions = [
+        Vec3D::new(0.0, 0.0, 1.0),
+        Vec3D::new(0.0, 0.0, -1.0),
+        Vec3D::new(1.0, 0.0, 0.0),
+        Vec3D::new(0.3, -0.8, 0.1),
+        Vec3D::new(1e-4, 0.0, -1.0),
+    ];
+    for direction in directions.iter().map(|d| d.unit_vector()) {
+        let (tangent, bitangent) = direction.orthonormal_basis();
+        assert!((tangent.length() - 1.0).abs() < 1e-5 && (bitangent.length() - 1.0).abs() < 1e-5);
+        assert!(Vec3D::dot(&tangent, &bitangent).abs() < 1e-5);
+        assert!(Vec3D::dot(&tangent, &direction).abs() < 1e-5);
+        assert!(Vec3D::dot(&bitangent, &direction).abs() < 1e-5);
+        // right handed
+        assert!((Vec3D::cross(&tangent, &bitangent) - direction).length() < 1e-5);
+    }
+}
+
+#[test]
+fn orthonormal_basis_converts_between_local_and_world() {
+    let direction = Vec3D::new(0.3, -0.8, 0.1).unit_vector();
+    let vector = Vec3D::new(0.2, -0.5, 0.7);
+    let back = direction.local_to_world(&direction.world_to_local(&vector));
+    assert!((back - vector).length() < 1e-5);
+    // z is the direction
+    let along = direction.local_to_world(&Vec3D::new(0.0, 0.0, 2.0));
+    assert!((along - 2.0 * direction).length() < 1e-5);
+}
+
+/// Mean of z and fraction of the directions with z > 0.5 over 100 000 samples,
+/// checking they're unit vectors
+fn z_moments(mut sample: impl FnMut(&mut rand::rngs::StdRng) -> Vec3D) -> (f64, f64) {
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(9);
+    let count = 100_000;
+    let (mut mean, mut above) = (0.0, 0.0);
+    for _ in 0..count {
+        let direction = sample(&mut rng);
+        assert!((direction.length() - 1.0).abs() < 1e-4);
+        mean += direction.z() as f64 / count as f64;
+        above += (direction.z() > 0.5) as u32 as f64 / count as f64;
+    }
+    (mean, above)
+}
+
+/// Integral over all the directions of a density depending on z (estimated with uniform directions)
+fn integrate_over_sphere(pdf: impl Fn(f32) -> f32) -> f64 {
+    use crate::grfx::sampling;
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(10);
+    let count = 100_000;
+    let sum: f64 = (0..count)
+        .map(|_| pdf(sampling::uniform_sphere(&mut rng).z()) as f64)
+        .sum();
+    sum / count as f64 / sampling::uniform_sphere_pdf() as f64
+}
+
+/// Unit directions a material scatters light to, lit at 45 degrees on a floor facing up
+fn scatter_at_45_degrees(
+    material: &dyn crate::grfx::material::Material,
+    count: usize,
+) -> Vec<Vec3D> {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::HitRecord;
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+    let mut record = HitRecord::new();
+    record.normal = Vec3D::new(0.0, 1.0, 0.0);
+    let ray = Ray::new(Vec3D::new(-1.0, 1.0, 0.0), Vec3D::new(1.0, -1.0, 0.0));
+    let mut directions = Vec::new();
+    for _ in 0..count {
+        let (mut attenuation, mut scattered) = (Rgb::BLACK, Ray::default());
+        assert!(material.scatter(&ray, &record, &mut attenuation, &mut scattered, &mut rng));
+        directions.push(scattered.direction().unit_vector());
+    }
+    directions
+}
+
+#[test]
+fn uniform_sphere_distribution() {
+    use crate::grfx::sampling;
+    let (mean, above) = z_moments(|rng| sampling::uniform_sphere(rng));
+    assert!(
+        mean.abs() < 0.01 && (above - 0.25).abs() < 0.01,
+        "{} {}",
+        mean,
+        above
+    );
+    let integral = integrate_over_sphere(|_| sampling::uniform_sphere_pdf());
+    assert!((integral - 1.0).abs() < 1e-6, "{}", integral);
+}
+
+#[test]
+fn uniform_hemisphere_distribution() {
+    use crate::grfx::sampling;
+    let (mean, above) = z_moments(|rng| sampling::uniform_hemisphere(rng));
+    assert!(
+        (mean - 0.5).abs() < 0.01 && (above - 0.5).abs() < 0.01,
+        "{} {}",
+        mean,
+        above
+    );
+    let integral = integrate_over_sphere(|z| {
+        if z >= 0.0 {
+            sampling::uniform_hemisphere_pdf()
+        } else {
+            0.0
+        }
+    });
+    assert!((integral - 1.0).abs() < 0.01, "{}", integral);
+}
+
+#[test]
+fn cosine_hemisphere_distribution() {
+    use crate::grfx::sampling;
+    // P(angle < theta) = sin²(theta)
+    let (mean, above) = z_moments(|rng| sampling::cosine_hemisphere(rng));
+    assert!(
+        (mean - 2.0 / 3.0).abs() < 0.01 && (above - 0.75).abs() < 0.01,
+        "{} {}",
+        mean,
+        above
+    );
+    let integral = integrate_over_sphere(sampling::cosine_hemisphere_pdf);
+    assert!((integral - 1.0).abs() < 0.01, "{}", integral);
+}
+
+#[test]
+fn uniform_cone_distribution() {
+    use crate::grfx::sampling;
+    // all the directions are within 60 degrees of z
+    let (mean, above) = z_moments(|rng| sampling::uniform_cone(0.5, rng));
+    assert!(
+        (mean - 0.75).abs() < 0.01 && (above - 1.0).abs() < 1e-9,
+        "{} {}",
+        mean,
+        above
+    );
+    let integral = integrate_over_sphere(|z| {
+        if z >= 0.5 {
+            sampling::uniform_cone_pdf(0.5)
+        } else {
+            0.0
+        }
+    });
+    assert!((integral - 1.0).abs() < 0.01, "{}", integral);
+}
+
+#[test]
+fn ggx_normal_distribution() {
+    use crate::grfx::sampling;
+    // P(tan²(theta) < alpha²) = 1/2, i.e. z > 1 / sqrt(1 + alpha²)
+    let alpha: f32 = 3.0f32.sqrt();
+    let (_, above) = z_moments(|rng| sampling::ggx_normal(alpha, rng));
+    assert!((above - 0.5).abs() < 0.01, "{}", above);
+}
+
+#[test]
+fn ggx_normal_pdf_integrates_to_one() {
+    use crate::grfx::sampling;
+    let integral = integrate_over_sphere(|z| sampling::ggx_normal_pdf(0.5, z));
+    assert!((integral - 1.0).abs() < 0.03, "{}", integral);
+}
+
+#[test]
+fn uniform_disk_distribution() {
+    use crate::grfx::sampling;
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(9);
+    let count = 100_000;
+    // the mean squared distance to the center of the unit disk is 1/2
+    let mut radius2 = 0.0;
+    for _ in 0..count {
+        let (x, y) = sampling::uniform_disk(&mut rng);
+        assert!(x * x + y * y <= 1.0);
+        radius2 += (x * x + y * y) as f64 / count as f64;
+    }
+    assert!((radius2 - 0.5).abs() < 0.01, "{}", radius2);
+}
+
+#[test]
+fn principled_sampling_matches_its_density() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::{Material, Principled};
+    use crate::grfx::ray::Ray;
+    use crate::grfx::sampling;
+    use crate::grfx::shape::HitRecord;
+    use rand::SeedableRng;
+    let mut record = HitRecord::new();
+    record.normal = Vec3D::new(0.0, 1.0, 0.0);
+    let ray = Ray::new(Vec3D::new(-1.0, 1.0, 0.0), Vec3D::new(1.0, -1.0, 0.0));
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    let count = 100_000;
+    let mut reflectances = Vec::new();
+    for (metallic, roughness) in [(1.0, 0.5), (0.0, 0.5), (0.0, 0.1), (0.5, 0.8)] {
+        let material = Principled::new(Rgb::new(0.9, 0.9, 0.9), metallic, roughness);
+        // the light reflected (directional albedo) estimated with the material sampling...
+        let mut sampled = 0.0;
+        for _ in 0..count {
+            let mut attenuation = Rgb::BLACK;
+            let mut scattered = Ray::default();
+            if material.scatter(&ray, &record, &mut attenuation, &mut scattered, &mut rng) {
+                sampled += attenuation.g() as f64 / count as f64;
+            }
+        }
+        // ...and with uniform directions agree, so scatter follows scattering_pdf
+        let mut uniform = 0.0;
+        for _ in 0..count {
+            let direction = sampling::uniform_hemisphere(&mut rng);
+            let scattered = Ray::new(record.point, Vec3D::new(direction.x(), direction.z(), direction.y()));
+            let value = material.eval(&ray, &record, &scattered).g() / sampling::uniform_hemisphere_pdf();
+            uniform += value as f64 / count as f64;
+        }
+        assert!((sampled - uniform).abs() < 0.03, "{} {}: {} vs {}", metallic, roughness, sampled, uniform);
+        // energy is never created
+        assert!(sampled < 1.0, "{} {}: {}", metallic, roughness, sampled);
+        reflectances.push(sampled);
+    }
+    // rough metals reflect most of the light, plastics a bit more than their diffuse part
+    assert!(reflectances[0] > 0.75 && reflectances[1] > 0.5, "{:?}", reflectances);
+}
+
+#[test]
+fn scene_principled_material_round_trips() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        material gold principled albedo=1,0.78,0.34 metallic=1 roughness=0.3 specular=0.5
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.materials.len(), 1);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn dispersion_formulas_give_catalog_indices() {
+    use crate::grfx::material::{Dialectric, Dispersion};
+    // BK7 glass catalog values
+    let bk7 = Dispersion::Sellmeier {
+        b: [1.039_612, 0.231_792_3, 1.010_469],
+        c: [0.006_000_7, 0.020_017_9, 103.560_6],
+    };
+    assert!((bk7.ior(587.6) - 1.5168).abs() < 1e-3, "{}", bk7.ior(587.6));
+    // blue bends more than red
+    assert!(bk7.ior(435.8) > bk7.ior(546.1) && bk7.ior(546.1) > bk7.ior(700.0));
+    let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.01 };
+    assert!((cauchy.ior(500.0) - 1.54).abs() < 1e-5);
+    assert!((Dialectric::dispersive(bk7).ior() - bk7.ior(587.6)).abs() < 1e-6);
+}
+
+#[test]
+fn dielectric_refracts_from_the_exterior_ior() {
+    use crate::grfx::material::Dialectric;
+    // Snell's law between water and glass: n1 sin(theta1) = n2 sin(theta2)
+    let glass = Dialectric::new(1.5).with_exterior_ior(1.33);
+    let sin_in = 0.5f32.sqrt();
+    let mut refracted = 0;
+    for direction in scatter_at_45_degrees(&glass, 100) {
+        if direction.y() < 0.0 {
+            assert!((1.33 * sin_in - 1.5 * direction.x()).abs() < 1e-4, "{:?}", direction);
+            refracted += 1;
+        }
+    }
+    assert!(refracted > 80);
+}
+
+#[test]
+fn tinted_dielectric_absorbs_along_the_distance() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::{Dialectric, Material};
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, Sphere};
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+    // the light leaving a ball from its center went through 1 unit of glass, keeping the tint
+    let material = Arc::new(Dialectric::new(1.5).with_tint(Rgb::new(0.5, 0.8, 1.0), 1.0));
+    let ball = Sphere::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, material.clone());
+    let inside = Ray::new(Vec3D::new(0.0, 0.0, 0.0), Vec3D::new(0.0, 0.0, 3.0));
+    let mut record = HitRecord::new();
+    assert!(ball.hit(&inside, 0.001, f32::MAX, &mut record));
+    let mut leaving = 0;
+    for _ in 0..100 {
+        let (mut attenuation, mut scattered) = (Rgb::BLACK, Ray::default());
+        material.scatter(&inside, &record, &mut attenuation, &mut scattered, &mut rng);
+        if scattered.direction().z() > 0.0 {
+            let error = attenuation - Rgb::new(0.5, 0.8, 1.0);
+            let close = error.r().abs() < 1e-4 && error.g().abs() < 1e-4 && error.b().abs() < 1e-4;
+            assert!(close, "{:?}", attenuation);
+            leaving += 1;
+        }
+    }
+    assert!(leaving > 0);
+}
+
+#[test]
+fn thin_walled_dielectric_doesnt_bend_light() {
+    use crate::grfx::material::Dialectric;
+    let window = Dialectric::new(1.5).thin_walled(0.01);
+    let incoming = Vec3D::new(1.0, -1.0, 0.0).unit_vector();
+    let mut through = 0;
+    for direction in scatter_at_45_degrees(&window, 100) {
+        if direction.y() < 0.0 {
+            assert!((direction - incoming).length() < 1e-5);
+            through += 1;
+        }
+    }
+    assert!(through > 80);
+}
+
+#[test]
+fn rgb_dispersion_keeps_the_channel_picked() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::material::{Dialectric, Dispersion, Material};
+    use crate::grfx::ray::Ray;
+    use crate::grfx::shape::{HitRecord, Hitable, Sphere};
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+    let prism = Arc::new(Dialectric::dispersive(Dispersion::Cauchy {
+        a: 1.5,
+        b: 0.02,
+    }));
+    let ball = Sphere::new(Vec3D::new(0.0, 0.0, 0.0), 1.0, prism.clone());
+    let ray = Ray::new(Vec3D::new(0.3, 0.0, -5.0), Vec3D::new(0.0, 0.0, 1.0));
+    for _ in 0..50 {
+        // the first interface picks a channel and tags the ray with its wavelength
+        let mut record = HitRecord::new();
+        assert!(ball.hit(&ray, 0.001, f32::MAX, &mut record));
+        let (mut attenuation, mut inside) = (Rgb::BLACK, Ray::default());
+        prism.scatter(&ray, &record, &mut attenuation, &mut inside, &mut rng);
+        let channel = [700.0, 546.1, 435.8]
+            .iter()
+            .position(|w| Some(*w) == inside.wavelength());
+        let expected = [
+            Rgb::new(3.0, 0.0, 0.0),
+            Rgb::new(0.0, 3.0, 0.0),
+            Rgb::new(0.0, 0.0, 3.0),
+        ];
+        assert_eq!(
+            attenuation,
+            expected[channel.expect("red, green or blue wavelength")]
+        );
+        // the next ones keep it, with nothing more to weight
+        if ball.hit(&inside, 0.001, f32::MAX, &mut record) {
+            let mut out = Ray::default();
+            prism.scatter(&inside, &record, &mut attenuation, &mut out, &mut rng);
+            assert_eq!(
+                (attenuation, out.wavelength()),
+                (Rgb::WHITE, inside.wavelength())
+            );
+        }
+    }
+}
+
+#[test]
+fn scene_dielectric_options_round_trip() {
+    use crate::grfx::scene::Scene;
+    let text = "
+        material prism dielectric exterior=1.33 tint=0.9,1,0.9 tint_distance=2 sellmeier=1.04,0.23,1.01,0.006,0.02,103.56
+        material window dielectric ior=1.5 thin=0.01
+        material flint dielectric cauchy=1.6,0.01
+    ";
+    let scene = Scene::parse(text).unwrap();
+    assert_eq!(scene.materials.len(), 3);
+    assert_eq!(Scene::parse(&scene.to_string()).unwrap(), scene);
+}
+
+#[test]
+fn scene_dielectric_errors() {
+    use crate::grfx::scene::Scene;
+    let glass = Scene::parse("material glass dielectric ior=1.5 cauchy=1.5");
+    assert_eq!(glass.unwrap_err().to_string(), "line 1: invalid value '1.5' for cauchy, expected 2 numbers");
+    // the ior of dispersive materials comes from their coefficients
+    let glass = Scene::parse("material glass dielectric ior=1.5 cauchy=1.5,0.01");
+    assert_eq!(glass.unwrap_err().to_string(), "line 1: give either an ior or dispersion coefficients");
+    for glass in ["ior=0", "ior=1.5 exterior=-1"] {
+        let error = Scene::parse(&format!("material glass dielectric {}", glass)).unwrap_err();
+        assert_eq!(error.to_string(), "line 1: dielectric ior has to be positive");
+    }
+}
+
+/// Wavelengths evenly spread over the traced range (3400 of them, 0.1 nm apart)
+fn visible_wavelengths() -> impl Iterator<Item = f32> {
+    use crate::grfx::spectrum;
+    let steps = 3400;
+    (0..steps).map(move |i| spectrum::sample_wavelength((i as f32 + 0.5) / steps as f32))
+}
+
+#[test]
+fn upsampled_spectra_go_back_to_their_colors() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::spectrum;
+    let colors = [
+        Rgb::WHITE,
+        Rgb::new(0.5, 0.5, 0.5),
+        Rgb::new(0.7, 0.1, 0.1),
+        Rgb::new(0.2, 0.6, 0.3),
+        Rgb::new(0.3, 0.7, 1.0),
+    ];
+    for color in colors {
+        let mut sum = Rgb::BLACK;
+        for wavelength in visible_wavelengths() {
+            let value = spectrum::from_rgb(&color, wavelength);
+            sum += spectrum::to_rgb(wavelength, value) / 3400.0;
+        }
+        let error = sum - color;
+        let close = error.r().abs() < 0.01 && error.g().abs() < 0.01 && error.b().abs() < 0.01;
+        assert!(close, "{:?} {:?}", color, sum);
+    }
+}
+
+#[test]
+fn upsampled_reflectances_stay_between_zero_and_one() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::spectrum;
+    for wavelength in visible_wavelengths() {
+        // white is flat
+        assert!((spectrum::from_rgb(&Rgb::WHITE, wavelength) - 1.0).abs() < 1e-4);
+        let red = spectrum::from_rgb(&Rgb::new(0.9, 0.1, 0.1), wavelength);
+        assert!((0.0..=1.0).contains(&red), "{} at {}", red, wavelength);
+    }
+}
+
+#[test]
+fn color_matching_functions_peak_where_expected() {
+    use crate::grfx::spectrum;
+    // luminance peaks in the green, 450 nm is blue and 650 nm red
+    assert!(spectrum::cie_xyz(555.0).y() > 0.99);
+    let blue = spectrum::cie_xyz(450.0);
+    assert!(blue.z() > blue.x() && blue.z() > blue.y());
+    let red = spectrum::to_rgb(650.0, 1.0);
+    assert!(red.r() > 0.0 && red.r() > red.g() && red.r() > red.b());
+}
+
+#[test]
+fn scene_spectral_setting_round_trips() {
+    use crate::grfx::scene::Scene;
+    let scene = Scene::parse("settings spectral=true").unwrap();
+    assert!(scene.settings.spectral);
+    assert!(Scene::parse(&scene.to_string()).unwrap().settings.spectral);
+    assert!(!Scene::parse("settings width=10").unwrap().settings.spectral);
+}
+
+#[test]
+fn spectral_renders_match_rgb_renders() {
+    use crate::grfx::color::Rgb;
+    use crate::grfx::render::Renderer;
+    use crate::grfx::scene::Scene;
+    let scene = Scene::parse(
+        "
+        settings width=4 height=4 samples=400 depth=8 seed=3
+        camera from=0,1,4 at=0,0.5,0 fov=30
+        background gradient bottom=1,1,1 top=0.5,0.7,1
+        material red lambertian albedo=0.8,0.2,0.1
+        material glass dielectric cauchy=1.5,0.01
+        plane point=0,0,0 normal=0,1,0 material=red
+        sphere center=0,0.5,0 radius=0.5 material=glass
+    ",
+    )
+    .unwrap();
+    let world = scene.world().unwrap();
+    let average = |spectral: bool| {
+        let mut settings = scene.settings.clone();
+        settings.spectral = spectral;
+        let image = Renderer::new(settings).render_hdr(&world, &scene.camera());
+        let mut sum = Rgb::BLACK;
+        for (x, y) in (0..4).flat_map(|y| (0..4).map(move |x| (x, y))) {
+            sum += image.get(x, y) / 16.0;
+        }
+        sum
+    };
+    // same colors on average, only the noise differs
+    let (spectral, rgb) = (average(true), average(false));
+    let error = spectral - rgb;
+    let close = error.r().abs() < 0.03 && error.g().abs() < 0.03 && error.b().abs() < 0.03;
+    assert!(close, "{:?} vs {:?}", spectral, rgb);
+}